@@ -0,0 +1,239 @@
+use std::time::Duration;
+
+use tokio::runtime::Runtime;
+
+use crate::cli::{describe_value, Status, StatusChange};
+use crate::config::{HeadsetConfig, NotificationConfig, NotificationEvent, NotificationTarget};
+use crate::devices::{Device, SettingId};
+use crate::hooks::{spawn_command, status_env};
+use crate::notification::{self, NotificationAction};
+use crate::util::fill_template;
+use crate::EventSender;
+
+/// How far the battery has to recover before a threshold can alert again
+const HYSTERESIS: u8 = 5;
+/// The level at which stopping to charge counts as finished
+const FULL_BATTERY: u8 = 100;
+
+/// Turns battery and charging changes into notification events
+#[derive(Debug, Default)]
+pub struct BatteryAlerts {
+    /// The lowest threshold that already alerted
+    alerted: Option<u8>
+}
+
+impl BatteryAlerts {
+    pub fn check(&mut self, config: &NotificationConfig, changes: &[StatusChange], status: &Status) -> Vec<NotificationEvent> {
+        let mut events = Vec::new();
+        for change in changes {
+            match change {
+                StatusChange::Connected => self.alerted = None,
+                StatusChange::BatteryChanged { current, .. } if !status.charging => {
+                    if let Some(level) = self.update(&config.battery_thresholds, *current) {
+                        tracing::info!("Battery reached the {}% threshold", level);
+                        events.push(NotificationEvent::LowBattery);
+                    }
+                }
+                StatusChange::ChargingStarted if status.connected => events.push(NotificationEvent::ChargingStarted),
+                StatusChange::ChargingFinished if status.connected => events.push(match fully_charged(status) {
+                    true => NotificationEvent::ChargingFinished,
+                    false => NotificationEvent::ChargingStopped
+                }),
+                _ => {}
+            }
+        }
+        events
+    }
+
+    /// Returns the threshold that was crossed, if it did not alert yet
+    fn update(&mut self, thresholds: &[u8], level: u8) -> Option<u8> {
+        let crossed = thresholds
+            .iter()
+            .copied()
+            .filter(|threshold| level <= *threshold)
+            .min();
+        match crossed {
+            Some(threshold) if self.alerted.map_or(true, |alerted| threshold < alerted) => {
+                self.alerted = Some(threshold);
+                Some(threshold)
+            }
+            _ => {
+                // Rearm every threshold the level recovered from
+                let pending = thresholds
+                    .iter()
+                    .copied()
+                    .filter(|threshold| !recovered(*threshold, level))
+                    .min();
+                self.alerted = self.alerted.and(pending);
+                None
+            }
+        }
+    }
+}
+
+/// Whether the level rose far enough above a threshold that already fired for it to fire again
+pub fn recovered(threshold: u8, level: u8) -> bool {
+    level >= threshold.saturating_add(HYSTERESIS)
+}
+
+/// Whether charging that stopped actually finished instead of being interrupted
+pub fn fully_charged(status: &Status) -> bool {
+    status.battery.map_or(false, |level| level >= FULL_BATTERY)
+}
+
+/// The buttons of the notification of an event
+pub fn actions(event: NotificationEvent, headset: &HeadsetConfig, low_power_profile: &str) -> Vec<NotificationAction> {
+    match event {
+        NotificationEvent::Connected => vec![NotificationAction::OpenSettings],
+        NotificationEvent::LowBattery => headset
+            .profiles
+            .iter()
+            .position(|profile| profile.name.eq_ignore_ascii_case(low_power_profile))
+            .filter(|index| *index as u32 != headset.selected_profile_index)
+            .map(|index| NotificationAction::SelectProfile(headset.profiles[index].name.clone()))
+            .into_iter()
+            .collect(),
+        _ => Vec::new()
+    }
+}
+
+/// Shows the notification of the event the way the user configured it, `extra` adds event specific placeholders
+pub fn deliver(
+    runtime: &Runtime, config: &NotificationConfig, event: NotificationEvent, status: &Status, extra: &[(&str, String)],
+    actions: Vec<NotificationAction>, events: &EventSender
+) {
+    let settings = config.event(event);
+    if !settings.enabled {
+        return;
+    }
+    let mut placeholders: Vec<(&str, String)> = status.placeholders().to_vec();
+    placeholders.extend_from_slice(extra);
+    let title = match settings.title.is_empty() {
+        true => event.default_title(),
+        false => settings.title.as_str()
+    };
+    let template = match settings.template.is_empty() {
+        true => event.default_template(),
+        false => settings.template.as_str()
+    };
+    let title = fill_template(title, &placeholders);
+    let body = fill_template(template, &placeholders);
+    match &settings.target {
+        NotificationTarget::Desktop => {
+            let duration = Duration::from_millis(settings.duration_ms as u64);
+            notification::notify_with_actions(&title, &body, duration, settings.urgency, actions, events.clone())
+                .unwrap_or_else(|err| tracing::warn!("Can not create notification: {:?}", err))
+        }
+        NotificationTarget::Log => tracing::info!("{}: {}", title, body),
+        NotificationTarget::Hook(command) => {
+            let mut env = status_env(status);
+            env.push((String::from("HEADSET_EVENT"), String::from("notification")));
+            env.push((String::from("HEADSET_NOTIFICATION"), event.name().to_string()));
+            env.push((String::from("HEADSET_TITLE"), title));
+            env.push((String::from("HEADSET_BODY"), body));
+            spawn_command(runtime, command, env);
+        }
+    }
+}
+
+/// The equalizer and side tone of the selected profile, the `{settings}` of [NotificationEvent::ProfileChanged]
+pub fn profile_summary(headset: &mut HeadsetConfig, device: &dyn Device) -> String {
+    device
+        .settings()
+        .iter()
+        .filter(|descriptor| matches!(descriptor.id, SettingId::Equalizer | SettingId::SideTone))
+        .map(|descriptor| {
+            let value = headset.setting(descriptor).clone();
+            format!("{}: {}", descriptor.label, describe_value(descriptor, &value, &headset.equalizer_presets))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(battery: u8, charging: bool) -> Status {
+        Status {
+            device: String::from("Headset"),
+            connected: true,
+            battery: (!charging).then_some(battery),
+            charging,
+            chat_mix: None,
+            profile: String::from("Default")
+        }
+    }
+
+    /// Feeds the levels one after another and collects the events
+    fn drain(alerts: &mut BatteryAlerts, levels: &[u8]) -> Vec<NotificationEvent> {
+        let config = NotificationConfig::default();
+        levels
+            .iter()
+            .flat_map(|level| {
+                let change = StatusChange::BatteryChanged { previous: None, current: *level };
+                alerts.check(&config, &[change], &status(*level, false))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_threshold_alerts_once() {
+        let mut alerts = BatteryAlerts::default();
+        let events = drain(&mut alerts, &[40, 31, 30, 29, 20, 15, 10, 5, 4]);
+        assert_eq!(events, vec![NotificationEvent::LowBattery; 3]);
+    }
+
+    #[test]
+    fn flapping_level_does_not_alert_again() {
+        let mut alerts = BatteryAlerts::default();
+        assert_eq!(drain(&mut alerts, &[30]), vec![NotificationEvent::LowBattery]);
+        assert!(drain(&mut alerts, &[31, 30, 33, 29, 34, 30]).is_empty());
+    }
+
+    #[test]
+    fn recovered_threshold_rearms() {
+        let mut alerts = BatteryAlerts::default();
+        assert_eq!(drain(&mut alerts, &[30]), vec![NotificationEvent::LowBattery]);
+        assert!(drain(&mut alerts, &[35]).is_empty());
+        assert_eq!(drain(&mut alerts, &[30]), vec![NotificationEvent::LowBattery]);
+    }
+
+    #[test]
+    fn partial_recovery_only_rearms_the_lower_threshold() {
+        let mut alerts = BatteryAlerts::default();
+        assert_eq!(drain(&mut alerts, &[30, 15]), vec![NotificationEvent::LowBattery; 2]);
+        assert!(drain(&mut alerts, &[25]).is_empty());
+        assert_eq!(drain(&mut alerts, &[15]), vec![NotificationEvent::LowBattery]);
+    }
+
+    #[test]
+    fn reconnect_rearms() {
+        let mut alerts = BatteryAlerts::default();
+        let config = NotificationConfig::default();
+        assert_eq!(drain(&mut alerts, &[30]), vec![NotificationEvent::LowBattery]);
+        assert!(alerts
+            .check(&config, &[StatusChange::Connected], &status(30, false))
+            .is_empty());
+        assert_eq!(drain(&mut alerts, &[30]), vec![NotificationEvent::LowBattery]);
+    }
+
+    #[test]
+    fn charging_level_does_not_alert() {
+        let mut alerts = BatteryAlerts::default();
+        let config = NotificationConfig::default();
+        let change = StatusChange::BatteryChanged { previous: None, current: 10 };
+        assert!(alerts
+            .check(&config, &[change], &status(10, true))
+            .is_empty());
+    }
+
+    #[test]
+    fn charging_only_finishes_with_a_full_battery() {
+        let mut alerts = BatteryAlerts::default();
+        let config = NotificationConfig::default();
+        let changes = [StatusChange::ChargingFinished];
+        assert_eq!(alerts.check(&config, &changes, &status(100, false)), vec![NotificationEvent::ChargingFinished]);
+        assert_eq!(alerts.check(&config, &changes, &status(60, false)), vec![NotificationEvent::ChargingStopped]);
+    }
+}
@@ -0,0 +1,748 @@
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use color_eyre::Result;
+use tokio::runtime::Runtime;
+
+use crate::alerts::{self, BatteryAlerts};
+use crate::audio::AudioSystem;
+use crate::auto_profile::AutoProfile;
+use crate::cli::{self, Status, StatusChange};
+use crate::config::{Config, HeadsetConfig, MicrophoneChain, NoiseCancellingMode, NotificationEvent, NotificationUrgency, SoftwareEqualizer};
+use crate::debouncer::{Action, Debouncer};
+use crate::devices::{BatteryLevel, BoxedDevice, DeviceManager, DeviceUpdate, SettingDescriptor, SettingId, SettingValue};
+use crate::hooks::Hooks;
+use crate::http::{self, ApiEvent, DeviceEvent, HttpServer};
+use crate::ipc::{IpcReply, IpcRequest, IpcResponse, IpcServer};
+use crate::midi::{self, MidiInput, MidiListener};
+use crate::mqtt::MqttPublisher;
+use crate::notification::NotificationAction;
+use crate::osc::OscServer;
+use crate::schedule::Scheduler;
+use crate::scripts::{ScriptCommand, Scripts};
+use crate::tray::{AppTray, TrayEvent};
+use crate::{
+    apply_config_to_device, lowest_battery_level, notification, select_profile, submit_full_change, update_tray, update_tray_tooltip,
+    AppEvent, EventSender
+};
+
+/// How often the commands of the script can trigger further commands while processing the actions once
+const MAX_SCRIPT_ROUNDS: usize = 8;
+
+/// Everything that keeps running independently of the window and the tray
+pub struct App {
+    pub runtime: Runtime,
+    pub config: Config,
+    pub device_manager: DeviceManager,
+    pub device: Option<BoxedDevice>,
+    pub audio_system: AudioSystem,
+    pub debouncer: Debouncer,
+    pub tray: Option<AppTray>,
+    events: EventSender,
+    last_connected: bool,
+    last_battery: Option<BatteryLevel>,
+    last_status: Option<Status>,
+    battery_alerts: BatteryAlerts,
+    hooks: Hooks,
+    ipc_server: Option<IpcServer>,
+    mqtt: Option<MqttPublisher>,
+    http_server: Option<HttpServer>,
+    osc_server: Option<OscServer>,
+    _midi_listener: Option<MidiListener>,
+    scripts: Scripts,
+    auto_profile: AutoProfile,
+    scheduler: Scheduler,
+    #[cfg(target_os = "linux")]
+    dbus_service: Option<crate::dbus::DbusService>
+}
+
+impl App {
+    pub fn new(runtime: Runtime, events: EventSender, dummy_device: bool) -> Result<Self> {
+        let mut config = Config::load()?;
+
+        let ipc_server = IpcServer::start(&runtime, events.clone())
+            .map_err(|err| tracing::warn!("Could not start the ipc server: {:?}", err))
+            .ok();
+
+        #[cfg(target_os = "linux")]
+        crate::notification::listen_for_actions(&runtime);
+        #[cfg(target_os = "linux")]
+        let dbus_service = crate::dbus::DbusService::start(&runtime, events.clone())
+            .map_err(|err| tracing::warn!("Could not start the dbus service: {:?}", err))
+            .ok();
+
+        let mqtt = config
+            .mqtt
+            .enabled
+            .then(|| MqttPublisher::start(&runtime, &config.mqtt, events.clone()));
+
+        let http_server = match config.http.enabled {
+            true => ensure_http_token(&mut config)
+                .and_then(|_| HttpServer::start(&runtime, &config.http, events.clone()))
+                .map_err(|err| tracing::warn!("Could not start the http server: {:?}", err))
+                .ok(),
+            false => None
+        };
+
+        let osc_server = match config.osc.enabled {
+            true => OscServer::start(&runtime, &config.osc, events.clone())
+                .map_err(|err| tracing::warn!("Could not start the osc server: {:?}", err))
+                .ok(),
+            false => None
+        };
+
+        let midi_listener = match config.midi.enabled {
+            true => MidiListener::start(events.clone())
+                .map_err(|err| tracing::warn!("Could not start the midi listener: {:?}", err))
+                .ok(),
+            false => None
+        };
+
+        let audio_system = AudioSystem::new();
+
+        let device_manager = runtime.block_on(DeviceManager::new(dummy_device))?;
+        let device = runtime.block_on(async {
+            device_manager
+                .find_preferred_device(&config.preferred_device, events.clone())
+                .await
+        });
+        #[cfg(target_os = "linux")]
+        check_device_access(&device_manager, device.as_ref(), &events);
+
+        let mut debouncer = Debouncer::new();
+        debouncer.submit_all([
+            Action::UpdateSystemAudio,
+            Action::UpdateSoftwareEqualizer,
+            Action::UpdateMicrophoneChain,
+            Action::UpdateTrayTooltip,
+            Action::UpdateTray,
+            Action::PublishStatus
+        ]);
+
+        Ok(Self {
+            runtime,
+            config,
+            device_manager,
+            device,
+            audio_system,
+            debouncer,
+            tray: None,
+            events,
+            last_connected: false,
+            last_battery: None,
+            last_status: None,
+            battery_alerts: BatteryAlerts::default(),
+            hooks: Hooks::default(),
+            ipc_server,
+            mqtt,
+            http_server,
+            osc_server,
+            _midi_listener: midi_listener,
+            scripts: Scripts::new(),
+            auto_profile: AutoProfile::new(),
+            scheduler: Scheduler::new(),
+            #[cfg(target_os = "linux")]
+            dbus_service
+        })
+    }
+
+    pub fn next_action(&self) -> Option<Instant> {
+        [
+            self.debouncer.next_action(),
+            self.scripts.next_tick(),
+            self.auto_profile.next_poll(),
+            self.scheduler.next_poll()
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    pub fn handle_device_update(&mut self, update: DeviceUpdate) {
+        if let Some(http_server) = &self.http_server {
+            let status = self
+                .device
+                .as_ref()
+                .map(|device| Status::new(device.as_ref(), &mut self.config));
+            http_server.publish(&ApiEvent::Device {
+                update: DeviceEvent::from(&update),
+                status
+            });
+        }
+        match update {
+            DeviceUpdate::ConnectionChanged | DeviceUpdate::BatteryLevel => {
+                self.debouncer
+                    .submit_all([Action::UpdateDeviceStatus, Action::PublishStatus])
+            }
+            DeviceUpdate::DeviceError(err) => tracing::error!("The device return an error: {}", err),
+            DeviceUpdate::ChatMixChanged => self.debouncer.submit(Action::PublishStatus)
+        }
+    }
+
+    /// Handles the tray entries that do not depend on the window
+    pub fn handle_tray_event(&mut self, event: TrayEvent) {
+        match event {
+            TrayEvent::Profile(id) => {
+                if let Some(device) = &self.device {
+                    select_profile(&mut self.debouncer, &mut self.config, device.as_ref(), id);
+                }
+            }
+            TrayEvent::NoiseCancelling(mode) => {
+                let _span = tracing::info_span!("noise_cancelling_change", ?mode).entered();
+                if let Some(device) = &self.device {
+                    let setting = device
+                        .settings()
+                        .into_iter()
+                        .find(|setting| setting.id == SettingId::NoiseCancelling);
+                    match setting {
+                        Some(setting) => {
+                            let choice = NoiseCancellingMode::MODES
+                                .iter()
+                                .position(|m| *m == mode)
+                                .expect("Mode should always exist") as u8;
+                            *self.config.get_headset(device.name()).setting(&setting) = SettingValue::Choice(choice);
+                            let action = Action::UpdateSetting(SettingId::NoiseCancelling);
+                            self.debouncer
+                                .submit_all([action, Action::SaveConfig, Action::UpdateTray]);
+                            self.debouncer.force(action);
+                        }
+                        None => tracing::warn!("Device does not support noise cancelling")
+                    }
+                }
+            }
+            TrayEvent::Open | TrayEvent::Quit => tracing::warn!("{:?} has to be handled by the window", event)
+        }
+    }
+
+    pub fn handle_midi_input(&mut self, input: MidiInput) {
+        let _span = tracing::info_span!("midi_input", ?input).entered();
+        if let Some(target) = self.config.midi.learn.take() {
+            tracing::info!("Assigned {} to {:?}", input.control, target);
+            self.config.midi.assign(input.control, target);
+            self.debouncer.submit(Action::SaveConfig);
+            return;
+        }
+        let (Some(target), Some(device)) = (self.config.midi.target(input.control), &self.device) else {
+            tracing::trace!("Unmapped midi control");
+            return;
+        };
+        if let Some(request) = midi::request(target, input.value, device.as_ref()) {
+            if let IpcResponse::Error { message, .. } = self.handle_ipc_request(request) {
+                tracing::warn!("Could not apply midi input: {}", message);
+            }
+        }
+    }
+
+    /// Handles every action except [NotificationAction::OpenSettings], which depends on the window
+    pub fn handle_notification_action(&mut self, action: NotificationAction) {
+        let _span = tracing::info_span!("notification_action", ?action).entered();
+        match action {
+            NotificationAction::OpenSettings => tracing::warn!("The application is running without a window"),
+            NotificationAction::SelectProfile(profile) => {
+                if let IpcResponse::Error { message, .. } = self.handle_ipc_request(IpcRequest::SelectProfile(profile)) {
+                    tracing::warn!("Could not select the profile: {}", message);
+                }
+            }
+            #[cfg(target_os = "linux")]
+            NotificationAction::InstallUdevRules => install_udev_rules(&self.runtime, self.events.clone()),
+            #[cfg(not(target_os = "linux"))]
+            NotificationAction::InstallUdevRules => tracing::warn!("Udev rules only exist on Linux")
+        }
+    }
+
+    pub fn refresh_devices(&mut self) {
+        self.debouncer
+            .submit_all([Action::RefreshDeviceList, Action::SwitchDevice]);
+    }
+
+    /// Answers the request through `reply`, an [IpcRequest::Flush] only once the headset received the pending changes
+    pub fn handle_ipc(&mut self, request: IpcRequest, reply: IpcReply) {
+        let send = |reply: IpcReply, response| {
+            reply
+                .send(response)
+                .unwrap_or_else(|_| tracing::warn!("Could not send the ipc response"))
+        };
+        let IpcRequest::Flush = request else {
+            return send(reply, self.handle_ipc_request(request));
+        };
+        self.process_actions();
+        match &self.device {
+            Some(device) => {
+                let flush = device.flush();
+                self.runtime.spawn(async move {
+                    flush.await;
+                    send(reply, IpcResponse::Ok);
+                });
+            }
+            None => send(reply, cli::no_device())
+        }
+    }
+
+    /// Handles every request except [IpcRequest::ShowWindow], which depends on the window
+    pub fn handle_ipc_request(&mut self, request: IpcRequest) -> IpcResponse {
+        let _span = tracing::info_span!("ipc_request", ?request).entered();
+        let changes_state = matches!(
+            request,
+            IpcRequest::SelectProfile(_)
+                | IpcRequest::SetSetting { .. }
+                | IpcRequest::SetSettingById { .. }
+                | IpcRequest::SetEqualizerBand { .. }
+        );
+        let response = self.respond(request.clone());
+        if let Some(http_server) = self.http_server.as_ref().filter(|_| changes_state) {
+            http_server.publish(&ApiEvent::Request {
+                request,
+                response: response.clone()
+            });
+        }
+        response
+    }
+
+    fn respond(&mut self, request: IpcRequest) -> IpcResponse {
+        let device = match (&request, &self.device) {
+            (IpcRequest::ShowWindow, _) => {
+                return IpcResponse::Error {
+                    code: 1,
+                    message: String::from("The application is running without a window")
+                }
+            }
+            (_, None) => return cli::no_device(),
+            (_, Some(device)) => device
+        };
+        match request {
+            IpcRequest::ShowWindow => unreachable!("Handled above"),
+            IpcRequest::Subscribe => IpcResponse::Error {
+                code: 1,
+                message: String::from("Subscriptions are only available on the control socket")
+            },
+            IpcRequest::Flush => IpcResponse::Error {
+                code: 1,
+                message: String::from("Flushing needs a reply channel")
+            },
+            IpcRequest::Status => IpcResponse::Status(Status::new(device.as_ref(), &mut self.config)),
+            IpcRequest::ListProfiles => IpcResponse::Profiles(cli::profile_entries(self.config.get_headset(device.name()))),
+            IpcRequest::ListSettings => IpcResponse::Settings(cli::setting_entries(self.config.get_headset(device.name()), device.as_ref())),
+            IpcRequest::SelectProfile(profile) => {
+                let headset = self.config.get_headset(device.name());
+                match cli::find_profile(headset, &profile) {
+                    Ok(index) => {
+                        let name = headset.profiles[index].name.clone();
+                        select_profile(&mut self.debouncer, &mut self.config, device.as_ref(), index);
+                        IpcResponse::Profile { index, name }
+                    }
+                    Err(response) => response
+                }
+            }
+            IpcRequest::SetSetting { setting, value } => {
+                let headset = self.config.get_headset(device.name());
+                let result = cli::update_setting(headset, device.as_ref(), &setting, &value);
+                submit_setting(&mut self.debouncer, headset, result)
+            }
+            IpcRequest::SetSettingById { id, value } => {
+                let headset = self.config.get_headset(device.name());
+                let result = cli::update_setting_by_id(headset, device.as_ref(), id, &value);
+                submit_setting(&mut self.debouncer, headset, result)
+            }
+            IpcRequest::SetEqualizerBand { band, level } => {
+                let headset = self.config.get_headset(device.name());
+                let result = cli::update_equalizer_band(headset, device.as_ref(), band, level);
+                submit_setting(&mut self.debouncer, headset, result)
+            }
+        }
+    }
+
+    /// Drives the application without a window system until an [AppEvent::Shutdown] arrives
+    pub fn run_headless(mut self, events: Receiver<AppEvent>) {
+        loop {
+            self.process_actions();
+            let event = match self.next_action() {
+                Some(deadline) => match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break
+                },
+                None => match events.recv() {
+                    Ok(event) => event,
+                    Err(_) => break
+                }
+            };
+            match event {
+                AppEvent::Device(update) => self.handle_device_update(update),
+                AppEvent::Ipc(request, reply) => self.handle_ipc(request, reply),
+                AppEvent::Midi(input) => self.handle_midi_input(input),
+                AppEvent::Notification(action) => self.handle_notification_action(action),
+                AppEvent::RefreshDevices => self.refresh_devices(),
+                // Only the window creates a tray
+                #[cfg(target_os = "linux")]
+                AppEvent::TrayHostAvailable => {}
+                AppEvent::Shutdown => break
+            }
+        }
+        self.debouncer.force(Action::SaveConfig);
+        self.process_actions();
+    }
+
+    pub fn process_actions(&mut self) {
+        self.scripts.tick(self.last_status.as_ref());
+        let automatic_profile = self.device.as_ref().and_then(|device| {
+            let headset = self.config.get_headset(device.name());
+            let pinned = headset.auto_profiles.pinned;
+            let index = self.auto_profile.poll(device.name(), headset);
+            if headset.auto_profiles.pinned != pinned {
+                self.debouncer.submit(Action::SaveConfig);
+            }
+            let index = index?;
+            tracing::info!("Automatically selecting profile {}", headset.profiles[index].name);
+            Some(index)
+        });
+        if let Some(index) = automatic_profile {
+            self.handle_tray_event(TrayEvent::Profile(index));
+        }
+        let scheduled = self
+            .device
+            .as_ref()
+            .map(|device| {
+                let schedules = &self.config.get_headset(device.name()).schedules;
+                self.scheduler.poll(device.name(), schedules)
+            })
+            .unwrap_or_default();
+        for request in scheduled {
+            if let IpcResponse::Error { message, .. } = self.handle_automated_request(request) {
+                tracing::warn!("Could not apply schedule: {}", message);
+            }
+        }
+        let mut rounds = 0;
+        loop {
+            while let Some(action) = self.debouncer.next() {
+                let _span = tracing::info_span!("debouncer_event", ?action).entered();
+                tracing::trace!("Processing event");
+                self.process_action(action);
+            }
+            let commands = self.scripts.take_commands();
+            if commands.is_empty() {
+                break;
+            }
+            if rounds == MAX_SCRIPT_ROUNDS {
+                tracing::warn!("The script keeps triggering itself, dropping {} commands", commands.len());
+                break;
+            }
+            rounds += 1;
+            for command in commands {
+                self.run_script_command(command);
+            }
+        }
+    }
+
+    /// Handles a request of a schedule or a script, whose profile changes must not pause the automatic switching
+    fn handle_automated_request(&mut self, request: IpcRequest) -> IpcResponse {
+        let response = self.handle_ipc_request(request);
+        if let (IpcResponse::Profile { index, .. }, Some(device)) = (&response, &self.device) {
+            self.auto_profile
+                .selected_by_automation(device.name(), *index as u32);
+        }
+        response
+    }
+
+    fn run_script_command(&mut self, command: ScriptCommand) {
+        let _span = tracing::info_span!("script_command", ?command).entered();
+        match command {
+            ScriptCommand::Request(request) => match self.handle_automated_request(request) {
+                IpcResponse::Profile { name, .. } => {
+                    let changed = self
+                        .last_status
+                        .as_ref()
+                        .map_or(false, |status| status.profile != name);
+                    self.scripts.profile_selected(changed);
+                }
+                IpcResponse::Error { message, .. } => tracing::warn!("Could not apply script command: {}", message),
+                _ => {}
+            },
+            ScriptCommand::Notify(text) => notification::notify("Headset Controller", &text, Duration::from_secs(2), NotificationUrgency::Normal)
+                .unwrap_or_else(|err| tracing::warn!("Can not create notification: {:?}", err))
+        }
+    }
+
+    fn process_action(&mut self, action: Action) {
+        let debouncer = &mut self.debouncer;
+        let config = &mut self.config;
+        match action {
+            Action::UpdateDeviceStatus => {
+                if let Some(device) = &self.device {
+                    let current_connection = device.is_connected();
+                    let current_battery = device.get_battery_status();
+                    if current_connection != self.last_connected {
+                        let mut status = Status::new(device.as_ref(), config);
+                        status.battery = lowest_battery_level(&[current_battery, self.last_battery]);
+                        let event = match current_connection {
+                            true => NotificationEvent::Connected,
+                            false => NotificationEvent::Disconnected
+                        };
+                        let low_power_profile = config.notifications.low_power_profile.clone();
+                        let actions = alerts::actions(event, config.get_headset(device.name()), &low_power_profile);
+                        alerts::deliver(&self.runtime, &config.notifications, event, &status, &[], actions, &self.events);
+                        debouncer.submit_all([
+                            Action::UpdateSystemAudio,
+                            Action::UpdateSoftwareEqualizer,
+                            Action::UpdateMicrophoneChain,
+                            Action::UpdateTrayTooltip
+                        ]);
+                        debouncer.force_all([Action::UpdateSystemAudio, Action::UpdateSoftwareEqualizer, Action::UpdateMicrophoneChain]);
+                        self.last_connected = current_connection;
+                    }
+                    if self.last_battery != current_battery {
+                        debouncer.submit(Action::UpdateTrayTooltip);
+                        self.last_battery = current_battery;
+                    }
+                }
+            }
+            Action::RefreshDeviceList => {
+                self.device = None;
+                let device_manager = &mut self.device_manager;
+                self.runtime.block_on(async {
+                    device_manager
+                        .refresh()
+                        .await
+                        .unwrap_or_else(|err| tracing::warn!("Failed to refresh devices: {}", err))
+                })
+            }
+            Action::SwitchDevice => {
+                if self.device.is_none() || config.preferred_device != self.device.as_ref().map(|d| d.name().to_string()) {
+                    let (device_manager, events) = (&self.device_manager, self.events.clone());
+                    self.device = self.runtime.block_on(async {
+                        device_manager
+                            .find_preferred_device(&config.preferred_device, events)
+                            .await
+                    });
+                    if let Some(device) = &self.device {
+                        submit_full_change(debouncer, device.as_ref());
+                    }
+                    #[cfg(target_os = "linux")]
+                    check_device_access(&self.device_manager, self.device.as_ref(), &self.events);
+                    debouncer.submit_all([Action::UpdateTray, Action::UpdateTrayTooltip, Action::PublishStatus]);
+                } else {
+                    tracing::debug!("Preferred device is already active")
+                }
+            }
+            Action::UpdateSystemAudio => {
+                if let Some(device) = &self.device {
+                    let headset = config.get_headset(device.name());
+                    self.audio_system
+                        .apply(&headset.os_audio, device.is_connected())
+                }
+            }
+            Action::PublishStatus => {
+                let status = self
+                    .device
+                    .as_ref()
+                    .map(|device| Status::new(device.as_ref(), config));
+                if let Some(status) = &status {
+                    let previous = self
+                        .last_status
+                        .as_ref()
+                        .filter(|previous| previous.device == status.device);
+                    let changes = status.changes_since(previous);
+                    self.hooks
+                        .run(&self.runtime, &config.hooks, &changes, status);
+                    let low_power_profile = config.notifications.low_power_profile.clone();
+                    for event in self
+                        .battery_alerts
+                        .check(&config.notifications, &changes, status)
+                    {
+                        let actions = match &self.device {
+                            Some(device) => alerts::actions(event, config.get_headset(device.name()), &low_power_profile),
+                            None => Vec::new()
+                        };
+                        alerts::deliver(&self.runtime, &config.notifications, event, status, &[], actions, &self.events);
+                    }
+                    let profile_changed = changes
+                        .iter()
+                        .any(|change| matches!(change, StatusChange::ProfileChanged { .. }));
+                    if let Some(device) = self.device.as_ref().filter(|_| profile_changed) {
+                        let summary = alerts::profile_summary(config.get_headset(device.name()), device.as_ref());
+                        let event = NotificationEvent::ProfileChanged;
+                        let extra = [("settings", summary)];
+                        alerts::deliver(&self.runtime, &config.notifications, event, status, &extra, Vec::new(), &self.events);
+                    }
+                    self.scripts.on_changes(&changes, status);
+                }
+                self.last_status = status.clone();
+                if let Some(ipc_server) = &self.ipc_server {
+                    ipc_server.publish(status.as_ref());
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(dbus_service) = &self.dbus_service {
+                    dbus_service.update(status.as_ref());
+                }
+                if let Some(osc_server) = &self.osc_server {
+                    osc_server.update(status.clone());
+                }
+                if let Some(mqtt) = &self.mqtt {
+                    let profiles = match &self.device {
+                        Some(device) => config
+                            .get_headset(device.name())
+                            .profiles
+                            .iter()
+                            .map(|profile| profile.name.clone())
+                            .collect(),
+                        None => Vec::new()
+                    };
+                    mqtt.update(status, profiles);
+                }
+            }
+            Action::UpdateSoftwareEqualizer => {
+                if let Some(device) = &self.device {
+                    let headset = config.get_headset(device.name());
+                    let equalizer = match device.is_connected() {
+                        true => headset.selected_profile().software_equalizer.clone(),
+                        false => SoftwareEqualizer::default()
+                    };
+                    self.audio_system
+                        .apply_software_equalizer(&equalizer, device.strings().product)
+                }
+            }
+            Action::UpdateMicrophoneChain => {
+                if let Some(device) = &self.device {
+                    let headset = config.get_headset(device.name());
+                    let microphone = match device.is_connected() {
+                        true => headset.selected_profile().microphone_chain.clone(),
+                        false => MicrophoneChain::default()
+                    };
+                    self.audio_system
+                        .apply_microphone_chain(&microphone, device.strings().product)
+                }
+            }
+            Action::SaveConfig => {
+                config
+                    .save()
+                    .unwrap_or_else(|err| tracing::warn!("Could not save config: {:?}", err));
+            }
+            Action::UpdateTray => {
+                if let Some(tray) = &mut self.tray {
+                    update_tray(tray, config, self.device.as_deref())
+                }
+                // The profile list is also offered over mqtt
+                debouncer.submit(Action::PublishStatus);
+            }
+            Action::UpdateTrayTooltip => {
+                if let Some(tray) = &mut self.tray {
+                    update_tray_tooltip(tray, &self.device)
+                }
+            }
+            Action::UpdateSetting(id) => {
+                if let Some(device) = &self.device {
+                    let headset = config.get_headset(device.name());
+                    apply_config_to_device(id, device.as_ref(), headset)
+                }
+                if id == SettingId::NoiseCancelling {
+                    debouncer.submit(Action::UpdateTray);
+                }
+            }
+        }
+    }
+}
+
+/// Applies a setting that was changed in the config right away
+fn submit_setting(debouncer: &mut Debouncer, headset: &mut HeadsetConfig, result: Result<SettingDescriptor, IpcResponse>) -> IpcResponse {
+    match result {
+        Ok(descriptor) => {
+            let action = Action::UpdateSetting(descriptor.id);
+            debouncer.submit_all([action, Action::SaveConfig, Action::UpdateTray]);
+            debouncer.force(action);
+            IpcResponse::Setting {
+                setting: descriptor.label.to_string(),
+                value: headset.setting(&descriptor).clone()
+            }
+        }
+        Err(response) => response
+    }
+}
+
+/// Resolves on Ctrl+C and, on unix, on SIGTERM so that the filter chains and the socket are cleaned up
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(err) => tracing::warn!("Could not listen for SIGTERM: {}", err)
+        }
+    }
+    tokio::signal::ctrl_c()
+        .await
+        .unwrap_or_else(|err| tracing::warn!("Could not listen for Ctrl+C: {}", err));
+}
+
+/// The http api must not run without a token, so one is generated and stored on first use
+fn ensure_http_token(config: &mut Config) -> Result<()> {
+    if config.http.token.is_empty() {
+        config.http.token = http::generate_token()?;
+        config
+            .save()
+            .unwrap_or_else(|err| tracing::warn!("Could not save config: {:?}", err));
+        tracing::info!("Generated a token for the http api, it is stored in {}", Config::path().display());
+    }
+    Ok(())
+}
+
+/// Offers to install the udev rules when a headset was found but could not be opened, which usually means they are missing
+#[cfg(target_os = "linux")]
+fn check_device_access(device_manager: &DeviceManager, device: Option<&BoxedDevice>, events: &EventSender) {
+    let Some(supported) = device_manager.supported_devices().first().filter(|_| device.is_none()) else {
+        return;
+    };
+    let body = format!("Could not open the {}, the udev rules might be missing", supported.name());
+    let actions = vec![NotificationAction::InstallUdevRules];
+    notification::notify_with_actions("Headset Controller", &body, Duration::from_secs(10), NotificationUrgency::Normal, actions, events.clone())
+        .unwrap_or_else(|err| tracing::warn!("Can not create notification: {:?}", err));
+}
+
+/// Writes the udev rules through `pkexec` and rescans the devices afterwards
+#[cfg(target_os = "linux")]
+fn install_udev_rules(runtime: &Runtime, events: EventSender) {
+    let rules = match crate::devices::generate_udev_rules() {
+        Ok(rules) => rules,
+        Err(err) => {
+            tracing::warn!("Could not generate the udev rules: {:?}", err);
+            return;
+        }
+    };
+    runtime.spawn(async move {
+        match write_udev_rules(rules).await {
+            Ok(()) => {
+                tracing::info!("Installed the udev rules");
+                // Give udev a moment to update the permissions
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                events
+                    .send_event(AppEvent::RefreshDevices)
+                    .unwrap_or_else(|_| tracing::warn!("Could not request a device refresh"));
+            }
+            Err(err) => tracing::warn!("Could not install the udev rules: {:?}", err)
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+async fn write_udev_rules(rules: String) -> Result<()> {
+    use std::process::Stdio;
+
+    use tokio::io::AsyncWriteExt;
+
+    const SCRIPT: &str = "cat > /etc/udev/rules.d/70-headset-controller.rules && udevadm control --reload-rules && udevadm trigger";
+    let mut child = tokio::process::Command::new("pkexec")
+        .args(["sh", "-c", SCRIPT])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin should be piped");
+    stdin.write_all(rules.as_bytes()).await?;
+    // Closing stdin ends cat
+    drop(stdin);
+    let status = child.wait().await?;
+    color_eyre::eyre::ensure!(status.success(), "pkexec exited with {}", status);
+    Ok(())
+}
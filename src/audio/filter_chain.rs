@@ -0,0 +1,504 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+use serde_json::Value;
+
+use crate::config::{runtime_dir, MicrophoneChain, SoftwareEqualizer};
+use crate::dsp::FilterKind;
+
+pub const SINK: &str = "Audio/Sink";
+pub const SOURCE: &str = "Audio/Source";
+
+/// How often the worker checks that the filter-chain is still running
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How often a crashed filter-chain is restarted before giving up until the next change
+const MAX_RESTARTS: u32 = 3;
+/// Searched for LADSPA plugins if `LADSPA_PATH` is not set
+const LADSPA_DIRS: &[&str] = &["/usr/lib/ladspa", "/usr/lib64/ladspa", "/usr/local/lib/ladspa", "/usr/lib/x86_64-linux-gnu/ladspa"];
+
+type ErrorSlot = Arc<Mutex<Option<String>>>;
+
+/// Describes the filter-chain that should run. The config is built once the node of the headset is known.
+pub struct ChainRequest {
+    media_class: &'static str,
+    product: String,
+    plugins: Vec<&'static str>,
+    build: Box<dyn FnOnce(&str) -> String + Send>
+}
+
+impl ChainRequest {
+    pub fn new(media_class: &'static str, product: &str, build: impl FnOnce(&str) -> String + Send + 'static) -> Self {
+        Self {
+            media_class,
+            product: product.to_string(),
+            plugins: Vec::new(),
+            build: Box::new(build)
+        }
+    }
+
+    /// The LADSPA plugins the config uses, which are checked before the filter-chain starts
+    pub fn with_plugins(mut self, plugins: Vec<&'static str>) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    fn resolve(self) -> Result<String> {
+        let missing = self
+            .plugins
+            .iter()
+            .copied()
+            .filter(|plugin| !ladspa_plugin_exists(plugin))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!("The LADSPA plugins {} are missing, please install the swh-plugins package", missing.join(", "));
+        }
+        let target = find_node(self.media_class, &self.product)?;
+        Ok((self.build)(&target))
+    }
+}
+
+/// Keeps a filter-chain running on its own thread, so that looking up the nodes never blocks the event loop
+pub struct FilterChainWorker {
+    sender: Option<Sender<Option<ChainRequest>>>,
+    thread: Option<JoinHandle<()>>,
+    error: ErrorSlot
+}
+
+impl FilterChainWorker {
+    pub fn spawn(name: &'static str) -> Self {
+        let (sender, receiver) = channel();
+        let error = ErrorSlot::default();
+        let thread = {
+            let error = error.clone();
+            std::thread::Builder::new()
+                .name(format!("filter-chain-{}", name))
+                .spawn(move || run(name, receiver, error))
+                .expect("Failed to spawn the filter-chain thread")
+        };
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+            error
+        }
+    }
+
+    /// Why the filter-chain is not running although it should
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+
+    /// Starts, updates or with `None` stops the filter-chain
+    pub fn update(&self, request: Option<ChainRequest>) {
+        if let Some(sender) = &self.sender {
+            sender
+                .send(request)
+                .unwrap_or_else(|_| tracing::warn!("The filter-chain thread is gone"));
+        }
+    }
+}
+
+impl Drop for FilterChainWorker {
+    fn drop(&mut self) {
+        // Closing the channel stops the thread, which stops the filter-chain
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            thread
+                .join()
+                .unwrap_or_else(|_| tracing::warn!("The filter-chain thread panicked"));
+        }
+    }
+}
+
+fn run(name: &'static str, receiver: Receiver<Option<ChainRequest>>, error: ErrorSlot) {
+    let _span = tracing::info_span!("filter_chain", name).entered();
+    let mut chain: Option<FilterChain> = None;
+    let mut restarts = 0;
+    loop {
+        match receiver.recv_timeout(CHECK_INTERVAL) {
+            Ok(request) => {
+                restarts = 0;
+                *error.lock().unwrap() = update_filter_chain(&mut chain, name, request.map(ChainRequest::resolve)).err();
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let Some(config) = chain.as_mut().filter(|chain| chain.has_exited()).map(|chain| chain.config.clone()) else {
+                    continue;
+                };
+                chain = None;
+                if restarts == MAX_RESTARTS {
+                    tracing::error!("The filter-chain keeps crashing, giving up");
+                    *error.lock().unwrap() = Some(String::from("The filter-chain keeps crashing, see the log for details"));
+                    continue;
+                }
+                restarts += 1;
+                tracing::warn!("The filter-chain stopped unexpectedly, restarting it");
+                chain = FilterChain::new(name, config)
+                    .map_err(|err| {
+                        tracing::warn!("Could not restart the filter-chain: {:?}", err);
+                        *error.lock().unwrap() = Some(err.to_string());
+                    })
+                    .ok();
+            }
+            Err(RecvTimeoutError::Disconnected) => break
+        }
+    }
+}
+
+/// Returns the reason if the filter-chain could not be started
+fn update_filter_chain(chain: &mut Option<FilterChain>, name: &str, config: Option<Result<String>>) -> Result<(), String> {
+    let config = match config {
+        Some(Ok(config)) => config,
+        Some(Err(err)) => {
+            tracing::warn!("Could not start the filter-chain: {:?}", err);
+            *chain = None;
+            return Err(err.to_string());
+        }
+        None => {
+            if chain.take().is_some() {
+                tracing::info!("Stopped the filter-chain");
+            }
+            return Ok(());
+        }
+    };
+    if chain.as_ref().map_or(false, |chain| chain.config == config) {
+        tracing::debug!("Filter-chain is already up to date");
+        return Ok(());
+    }
+    // The old chain has to be gone before the new one registers its node under the same name
+    *chain = None;
+    match FilterChain::new(name, config) {
+        Ok(started) => {
+            tracing::info!("Started the filter-chain");
+            *chain = Some(started);
+            Ok(())
+        }
+        Err(err) => {
+            tracing::warn!("Could not start the filter-chain: {:?}", err);
+            Err(err.to_string())
+        }
+    }
+}
+
+struct FilterChain {
+    config: String,
+    process: Child
+}
+
+impl FilterChain {
+    fn new(name: &str, config: String) -> Result<Self> {
+        let path = config_path(name)?;
+        std::fs::write(&path, &config)?;
+        let process = Command::new("pipewire")
+            .arg("-c")
+            .arg(&path)
+            .stdin(Stdio::null())
+            .spawn()?;
+        tracing::debug!("Started filter-chain with pid {}", process.id());
+        Ok(Self { config, process })
+    }
+
+    fn has_exited(&mut self) -> bool {
+        match self.process.try_wait() {
+            Ok(Some(status)) => {
+                tracing::debug!("The filter-chain exited with {}", status);
+                true
+            }
+            Ok(None) => false,
+            Err(err) => {
+                tracing::warn!("Could not check the filter-chain: {}", err);
+                false
+            }
+        }
+    }
+}
+
+impl Drop for FilterChain {
+    fn drop(&mut self) {
+        tracing::debug!("Stopping filter-chain with pid {}", self.process.id());
+        self.process
+            .kill()
+            .and_then(|_| self.process.wait())
+            .map(|_| ())
+            .unwrap_or_else(|err| tracing::warn!("Could not stop the filter-chain: {}", err));
+    }
+}
+
+fn ladspa_plugin_exists(plugin: &str) -> bool {
+    let file = format!("{}.so", plugin);
+    match std::env::var_os("LADSPA_PATH") {
+        Some(paths) => std::env::split_paths(&paths).any(|dir| dir.join(&file).exists()),
+        None => LADSPA_DIRS.iter().any(|dir| Path::new(dir).join(&file).exists())
+    }
+}
+
+/// Lives in the private runtime directory, so no other user can swap the config
+fn config_path(name: &str) -> std::io::Result<PathBuf> {
+    Ok(runtime_dir()?.join(format!("headset-controller-{}.conf", name)))
+}
+
+struct Node {
+    name: String,
+    plugin: Option<&'static str>,
+    label: &'static str,
+    controls: Vec<(&'static str, f64)>
+}
+
+impl Node {
+    fn builtin(name: impl Into<String>, label: &'static str, controls: Vec<(&'static str, f64)>) -> Self {
+        Self {
+            name: name.into(),
+            plugin: None,
+            label,
+            controls
+        }
+    }
+
+    fn ladspa(name: impl Into<String>, plugin: &'static str, label: &'static str, controls: Vec<(&'static str, f64)>) -> Self {
+        Self {
+            name: name.into(),
+            plugin: Some(plugin),
+            label,
+            controls
+        }
+    }
+
+    fn gain(name: impl Into<String>, gain: f64) -> Self {
+        // A shelf at 0 Hz affects the whole spectrum, which turns it into a plain gain stage
+        Self::builtin(name, "bq_highshelf", vec![("Freq", 0.0), ("Q", 1.0), ("Gain", gain)])
+    }
+}
+
+pub fn equalizer_config(equalizer: &SoftwareEqualizer, target: &str) -> String {
+    let mut nodes = Vec::new();
+    if equalizer.preamp != 0.0 {
+        nodes.push(Node::gain("preamp", equalizer.preamp));
+    }
+    for (i, filter) in equalizer.filters.iter().enumerate() {
+        let label = match filter.kind {
+            FilterKind::Peaking => "bq_peaking",
+            FilterKind::LowShelf => "bq_lowshelf",
+            FilterKind::HighShelf => "bq_highshelf"
+        };
+        let controls = vec![("Freq", filter.frequency), ("Q", filter.q), ("Gain", filter.gain)];
+        nodes.push(Node::builtin(format!("eq_band_{}", i + 1), label, controls));
+    }
+    let capture = [("node.name", "headset_controller_equalizer"), ("media.class", SINK)];
+    let playback = [
+        ("node.name", "headset_controller_equalizer.output"),
+        ("node.passive", "true"),
+        ("target.object", target)
+    ];
+    generate_config("Headset Controller Equalizer", &nodes, &["FL", "FR"], &capture, &playback)
+}
+
+/// The LADSPA plugins used by [microphone_config]
+pub fn microphone_plugins(microphone: &MicrophoneChain) -> Vec<&'static str> {
+    [(microphone.noise_gate, "gate_1410"), (microphone.compressor, "sc4m_1916")]
+        .into_iter()
+        .filter(|(used, _)| *used)
+        .map(|(_, plugin)| plugin)
+        .collect()
+}
+
+pub fn microphone_config(microphone: &MicrophoneChain, target: &str) -> String {
+    let mut nodes = Vec::new();
+    if microphone.noise_gate {
+        let controls = vec![("Threshold (dB)", microphone.gate_threshold), ("Attack (ms)", 5.0), ("Hold (ms)", 150.0), ("Decay (ms)", 200.0)];
+        nodes.push(Node::ladspa("gate", "gate_1410", "gate", controls));
+    }
+    if microphone.compressor {
+        let controls = vec![
+            ("Attack time (ms)", 10.0),
+            ("Release time (ms)", 150.0),
+            ("Threshold level (dB)", microphone.compressor_threshold),
+            ("Ratio (1:n)", microphone.compressor_ratio)
+        ];
+        nodes.push(Node::ladspa("compressor", "sc4m_1916", "sc4m", controls));
+    }
+    // Keep a gain stage even at 0 dB so that the graph is never empty
+    nodes.push(Node::gain("gain", microphone.gain));
+    let capture = [
+        ("node.name", "headset_controller_microphone.input"),
+        ("node.passive", "true"),
+        ("target.object", target)
+    ];
+    let playback = [("node.name", "headset_controller_microphone"), ("media.class", SOURCE)];
+    generate_config("Headset Controller Microphone", &nodes, &["MONO"], &capture, &playback)
+}
+
+fn generate_config(description: &str, nodes: &[Node], channels: &[&str], capture: &[(&str, &str)], playback: &[(&str, &str)]) -> String {
+    let mut config = String::new();
+    let _ = writeln!(config, "context.properties = {{ log.level = 0 }}");
+    let _ = writeln!(config, "context.spa-libs = {{");
+    let _ = writeln!(config, "    audio.convert.* = audioconvert/libspa-audioconvert");
+    let _ = writeln!(config, "    support.*       = support/libspa-support");
+    let _ = writeln!(config, "}}");
+    let _ = writeln!(config, "context.modules = [");
+    let _ = writeln!(config, "    {{ name = libpipewire-module-rt flags = [ ifexists nofail ] }}");
+    let _ = writeln!(config, "    {{ name = libpipewire-module-protocol-native }}");
+    let _ = writeln!(config, "    {{ name = libpipewire-module-client-node }}");
+    let _ = writeln!(config, "    {{ name = libpipewire-module-adapter }}");
+    let _ = writeln!(config, "    {{ name = libpipewire-module-filter-chain");
+    let _ = writeln!(config, "        args = {{");
+    let _ = writeln!(config, r#"            node.description = "{}""#, description);
+    let _ = writeln!(config, r#"            media.name = "{}""#, description);
+    let _ = writeln!(config, "            filter.graph = {{");
+    let _ = writeln!(config, "                nodes = [");
+    for node in nodes {
+        let plugin = node
+            .plugin
+            .map_or_else(|| String::from("type = builtin"), |plugin| format!("type = ladspa plugin = {}", plugin));
+        let controls = node
+            .controls
+            .iter()
+            .map(|(name, value)| format!(r#""{}" = {:.3}"#, name, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(
+            config,
+            "                    {{ {} name = {} label = {} control = {{ {} }} }}",
+            plugin, node.name, node.label, controls
+        );
+    }
+    let _ = writeln!(config, "                ]");
+    let _ = writeln!(config, "                links = [");
+    for link in nodes.windows(2) {
+        let (output, input) = (&link[0], &link[1]);
+        let _ = writeln!(
+            config,
+            r#"                    {{ output = "{}:{}" input = "{}:{}" }}"#,
+            output.name,
+            port_name(output, "Out"),
+            input.name,
+            port_name(input, "In")
+        );
+    }
+    let _ = writeln!(config, "                ]");
+    let _ = writeln!(config, "            }}");
+    let _ = writeln!(config, "            audio.channels = {}", channels.len());
+    let _ = writeln!(config, "            audio.position = [ {} ]", channels.join(" "));
+    for (section, props) in [("capture.props", capture), ("playback.props", playback)] {
+        let _ = writeln!(config, "            {} = {{", section);
+        for (key, value) in props {
+            let _ = writeln!(config, r#"                {} = "{}""#, key, value);
+        }
+        let _ = writeln!(config, "            }}");
+    }
+    let _ = writeln!(config, "        }}");
+    let _ = writeln!(config, "    }}");
+    let _ = writeln!(config, "]");
+    config
+}
+
+fn port_name(node: &Node, builtin: &'static str) -> &'static str {
+    match (node.plugin, builtin) {
+        (None, port) => port,
+        (Some(_), "In") => "Input",
+        (Some(_), _) => "Output"
+    }
+}
+
+fn find_node(media_class: &str, product: &str) -> Result<String> {
+    let output = Command::new("pw-dump").stderr(Stdio::null()).output()?;
+    let objects: Vec<Value> = serde_json::from_slice(&output.stdout)?;
+    let needle = product.to_lowercase();
+    objects
+        .iter()
+        .filter_map(|object| object.pointer("/info/props"))
+        .filter(|props| props["media.class"] == media_class)
+        .find(|props| {
+            props["node.description"]
+                .as_str()
+                .map_or(false, |description| description.to_lowercase().contains(&needle))
+        })
+        .and_then(|props| props["node.name"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| eyre!("Could not find a {} node for {}", media_class, product))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::ParametricFilter;
+
+    #[test]
+    fn config_links_nodes_in_order() {
+        let nodes = [
+            Node::gain("first", -3.0),
+            Node::ladspa("second", "plugin_1", "plugin", vec![("Level", 1.5)]),
+            Node::gain("third", 0.0)
+        ];
+        let config = generate_config("Test", &nodes, &["FL", "FR"], &[("node.name", "in")], &[("node.name", "out")]);
+        assert!(config.contains(r#"{ type = builtin name = first label = bq_highshelf control = { "Freq" = 0.000 "Q" = 1.000 "Gain" = -3.000 } }"#));
+        assert!(config.contains(r#"{ type = ladspa plugin = plugin_1 name = second label = plugin control = { "Level" = 1.500 } }"#));
+        assert!(config.contains(r#"{ output = "first:Out" input = "second:Input" }"#));
+        assert!(config.contains(r#"{ output = "second:Output" input = "third:In" }"#));
+        assert_eq!(config.matches("output = ").count(), 2);
+        assert!(config.contains("audio.channels = 2"));
+        assert!(config.contains("audio.position = [ FL FR ]"));
+        assert!(config.contains(r#"node.description = "Test""#));
+        assert!(config.contains("capture.props = {\n                node.name = \"in\""));
+        assert!(config.contains("playback.props = {\n                node.name = \"out\""));
+        assert_eq!(config.matches('{').count(), config.matches('}').count());
+    }
+
+    #[test]
+    fn equalizer_config_contains_preamp_and_bands() {
+        let equalizer = SoftwareEqualizer {
+            enabled: true,
+            preamp: -6.0,
+            filters: vec![ParametricFilter {
+                kind: FilterKind::LowShelf,
+                frequency: 105.0,
+                q: 0.7,
+                gain: 4.5
+            }]
+        };
+        let config = equalizer_config(&equalizer, "alsa_output.headset");
+        assert!(config.contains(r#"name = preamp label = bq_highshelf control = { "Freq" = 0.000 "Q" = 1.000 "Gain" = -6.000 }"#));
+        assert!(config.contains(r#"name = eq_band_1 label = bq_lowshelf control = { "Freq" = 105.000 "Q" = 0.700 "Gain" = 4.500 }"#));
+        assert!(config.contains(r#"{ output = "preamp:Out" input = "eq_band_1:In" }"#));
+        assert!(config.contains(r#"target.object = "alsa_output.headset""#));
+        assert!(config.contains(&format!(r#"media.class = "{}""#, SINK)));
+    }
+
+    #[test]
+    fn microphone_config_chains_gate_compressor_and_gain() {
+        let microphone = MicrophoneChain {
+            enabled: true,
+            gain: 3.0,
+            ..Default::default()
+        };
+        let config = microphone_config(&microphone, "alsa_input.headset");
+        assert!(config.contains(r#"type = ladspa plugin = gate_1410 name = gate label = gate control = { "Threshold (dB)" = -50.000"#));
+        assert!(config.contains(r#"type = ladspa plugin = sc4m_1916 name = compressor label = sc4m"#));
+        assert!(config.contains(r#""Threshold level (dB)" = -20.000 "Ratio (1:n)" = 4.000"#));
+        assert!(config.contains(r#"{ output = "gate:Output" input = "compressor:Input" }"#));
+        assert!(config.contains(r#"{ output = "compressor:Output" input = "gain:In" }"#));
+        assert!(config.contains("audio.position = [ MONO ]"));
+        assert!(config.contains(r#"target.object = "alsa_input.headset""#));
+        assert!(config.contains(&format!(r#"media.class = "{}""#, SOURCE)));
+        assert_eq!(microphone_plugins(&microphone), ["gate_1410", "sc4m_1916"]);
+    }
+
+    #[test]
+    fn microphone_config_without_plugins_keeps_the_gain() {
+        let microphone = MicrophoneChain {
+            enabled: true,
+            noise_gate: false,
+            compressor: false,
+            ..Default::default()
+        };
+        let config = microphone_config(&microphone, "alsa_input.headset");
+        assert!(!config.contains("type = ladspa"));
+        assert!(config.contains(r#"name = gain label = bq_highshelf control = { "Freq" = 0.000 "Q" = 1.000 "Gain" = 0.000 }"#));
+        assert!(!config.contains("output = "));
+        assert!(microphone_plugins(&microphone).is_empty());
+    }
+}
@@ -6,15 +6,22 @@ mod platform;
 #[path = "platforms/dummy.rs"]
 mod platform;
 
+#[cfg(target_os = "linux")]
+mod filter_chain;
+
 pub use platform::{AudioDevice, AudioLoopback, AudioManager};
 
-use crate::config::OsAudio;
+use crate::config::{MicrophoneChain, OsAudio, SoftwareEqualizer};
 
 pub struct AudioSystem {
     manager: Option<AudioManager>,
     devices: Vec<AudioDevice>,
     default_device: Option<AudioDevice>,
-    loopback: Option<AudioLoopback>
+    loopback: Option<AudioLoopback>,
+    #[cfg(target_os = "linux")]
+    software_equalizer: filter_chain::FilterChainWorker,
+    #[cfg(target_os = "linux")]
+    microphone_chain: filter_chain::FilterChainWorker
 }
 
 impl AudioSystem {
@@ -26,7 +33,11 @@ impl AudioSystem {
             manager,
             devices: Vec::new(),
             default_device: None,
-            loopback: None
+            loopback: None,
+            #[cfg(target_os = "linux")]
+            software_equalizer: filter_chain::FilterChainWorker::spawn("equalizer"),
+            #[cfg(target_os = "linux")]
+            microphone_chain: filter_chain::FilterChainWorker::spawn("microphone")
         };
         result.refresh_devices();
         result
@@ -92,4 +103,52 @@ impl AudioSystem {
             }
         }
     }
+
+    pub fn supports_filter_chains() -> bool {
+        cfg!(target_os = "linux")
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn apply_software_equalizer(&mut self, equalizer: &SoftwareEqualizer, product: &str) {
+        let request = equalizer.is_active().then(|| {
+            let equalizer = equalizer.clone();
+            filter_chain::ChainRequest::new(filter_chain::SINK, product, move |target| filter_chain::equalizer_config(&equalizer, target))
+        });
+        self.software_equalizer.update(request);
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn apply_microphone_chain(&mut self, microphone: &MicrophoneChain, product: &str) {
+        let request = microphone.enabled.then(|| {
+            let plugins = filter_chain::microphone_plugins(microphone);
+            let microphone = microphone.clone();
+            filter_chain::ChainRequest::new(filter_chain::SOURCE, product, move |target| filter_chain::microphone_config(&microphone, target))
+                .with_plugins(plugins)
+        });
+        self.microphone_chain.update(request);
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn microphone_chain_error(&self) -> Option<String> {
+        self.microphone_chain.error()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply_software_equalizer(&mut self, equalizer: &SoftwareEqualizer, _product: &str) {
+        if equalizer.is_active() {
+            tracing::warn!("The software equalizer is not supported on this platform");
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply_microphone_chain(&mut self, microphone: &MicrophoneChain, _product: &str) {
+        if microphone.enabled {
+            tracing::warn!("The microphone chain is not supported on this platform");
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn microphone_chain_error(&self) -> Option<String> {
+        None
+    }
 }
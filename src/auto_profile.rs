@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use color_eyre::Result;
+
+use crate::config::HeadsetConfig;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Selects profiles based on the running processes.
+///
+/// Only whether a process runs is considered, not which window has the focus,
+/// as there is no way to query the focused window that works on every Wayland compositor.
+pub struct AutoProfile {
+    next_poll: Instant,
+    enabled: bool,
+    /// The device and the profile that was selected by the last poll or by another automation
+    applied: Option<(String, u32)>,
+    scanner: Option<ProcessScanner>,
+    /// Set when the scanner could not be started, which disables the switching until the next start
+    unavailable: bool
+}
+
+impl AutoProfile {
+    pub fn new() -> Self {
+        Self {
+            next_poll: Instant::now(),
+            enabled: false,
+            applied: None,
+            scanner: None,
+            unavailable: false
+        }
+    }
+
+    /// Records a profile selected by a schedule or a script, so it is not taken for a manual change
+    pub fn selected_by_automation(&mut self, device: &str, index: u32) {
+        self.applied = Some((device.to_string(), index));
+    }
+
+    pub fn next_poll(&self) -> Option<Instant> {
+        self.enabled.then_some(self.next_poll)
+    }
+
+    /// Returns the profile that should be selected, if it differs from the current one.
+    ///
+    /// A profile that was changed by something else since the last poll pins the profile.
+    pub fn poll(&mut self, device: &str, headset: &mut HeadsetConfig) -> Option<usize> {
+        let config = &mut headset.auto_profiles;
+        self.enabled = config.enabled && !self.unavailable;
+        if !self.enabled {
+            self.scanner = None;
+            return None;
+        }
+        if self.next_poll > Instant::now() {
+            return None;
+        }
+        self.next_poll = Instant::now() + POLL_INTERVAL;
+        let selected = headset.selected_profile_index;
+        let changed_by_hand = self
+            .applied
+            .as_ref()
+            .map_or(false, |(name, index)| name == device && *index != selected);
+        if changed_by_hand && !config.pinned {
+            tracing::info!("The profile was changed manually, pausing automatic switching");
+            config.pinned = true;
+        }
+        if config.pinned {
+            self.applied = None;
+            return None;
+        }
+        if self.scanner.is_none() {
+            match ProcessScanner::spawn() {
+                Ok(scanner) => self.scanner = Some(scanner),
+                Err(err) => {
+                    tracing::warn!("Could not start the process scanner, disabling automatic profiles: {:?}", err);
+                    self.unavailable = true;
+                    self.enabled = false;
+                    return None;
+                }
+            }
+        }
+        let processes = self.scanner.as_ref()?.latest()?;
+        let target = config
+            .rules
+            .iter()
+            .find(|rule| processes.contains(&rule.process.to_lowercase()))
+            .map(|rule| &rule.profile)
+            .or(config.fallback.as_ref())
+            .and_then(|name| match headset.profiles.iter().position(|p| p.name == *name) {
+                Some(index) => Some(index),
+                None => {
+                    tracing::warn!("Unknown profile \"{}\" in automatic profile rules", name);
+                    None
+                }
+            });
+        let target = target.filter(|index| *index as u32 != selected);
+        self.applied = Some((device.to_string(), target.map_or(selected, |index| index as u32)));
+        target
+    }
+}
+
+/// Scans the running processes on a background thread, reading `/proc` takes too long for the event loop
+struct ProcessScanner {
+    processes: Arc<Mutex<Option<HashSet<String>>>>,
+    // Dropping the sender stops the thread
+    _stop: mpsc::Sender<()>
+}
+
+impl ProcessScanner {
+    fn spawn() -> Result<Self> {
+        let processes = Arc::new(Mutex::new(None));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let shared = processes.clone();
+        thread::Builder::new()
+            .name("process-scanner".to_string())
+            .spawn(move || loop {
+                let scanned = running_processes();
+                *shared.lock().unwrap() = scanned;
+                if stopped.recv_timeout(POLL_INTERVAL) != Err(RecvTimeoutError::Timeout) {
+                    break;
+                }
+            })?;
+        Ok(Self { processes, _stop: stop })
+    }
+
+    /// The result of the last scan, `None` if it was already taken or the scan failed
+    fn latest(&self) -> Option<HashSet<String>> {
+        self.processes.lock().unwrap().take()
+    }
+}
+
+/// The lowercase names of all running executables
+#[cfg(target_os = "linux")]
+fn running_processes() -> Option<HashSet<String>> {
+    let entries = std::fs::read_dir("/proc")
+        .map_err(|err| tracing::warn!("Can not read /proc: {}", err))
+        .ok()?;
+    let processes = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .map_or(false, |name| name.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .flat_map(|entry| {
+            // comm is truncated to 15 characters, so also check the name of the first argument
+            let comm = std::fs::read_to_string(entry.path().join("comm"))
+                .ok()
+                .map(|comm| comm.trim_end().to_string());
+            let exe = std::fs::read(entry.path().join("cmdline"))
+                .ok()
+                .and_then(|cmdline| {
+                    let arg = cmdline.split(|b| *b == 0).next()?;
+                    let arg = String::from_utf8_lossy(arg);
+                    arg.rsplit(['/', '\\']).next().map(str::to_string)
+                });
+            comm.into_iter().chain(exe)
+        })
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_lowercase())
+        .collect();
+    Some(processes)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn running_processes() -> Option<HashSet<String>> {
+    tracing::debug!("Automatic profile switching is only supported on Linux");
+    None
+}
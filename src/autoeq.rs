@@ -0,0 +1,287 @@
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+
+use crate::devices::Equalizer;
+use crate::dsp::{combined_response, log_frequencies, Biquad, FilterKind, ParametricFilter};
+
+const FIT_POINTS: usize = 128;
+const FIT_ITERATIONS: usize = 32;
+const DEFAULT_Q: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParametricEq {
+    /// A flat gain in dB on top of the filters, usually negative to avoid clipping
+    pub preamp: f64,
+    pub filters: Vec<ParametricFilter>
+}
+
+impl ParametricEq {
+    /// The response in dB including the preamp
+    pub fn response(&self, frequency: f64) -> f64 {
+        let filters = self
+            .filters
+            .iter()
+            .map(|f| f.biquad())
+            .collect::<Vec<_>>();
+        self.preamp + combined_response(&filters, frequency)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EqualizerFit {
+    pub levels: Vec<u8>,
+    pub rms_error: f64,
+    pub max_error: f64
+}
+
+pub fn parse_parametric_eq(text: &str) -> Result<ParametricEq> {
+    let mut result = ParametricEq {
+        preamp: 0.0,
+        filters: Vec::new()
+    };
+    for (number, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut tokens = value.split_whitespace();
+        if key.trim().eq_ignore_ascii_case("Preamp") {
+            result.preamp = parse_number(tokens.next(), number)?;
+        } else if key.trim().starts_with("Filter") {
+            if !tokens
+                .next()
+                .map_or(false, |state| state.eq_ignore_ascii_case("ON"))
+            {
+                continue;
+            }
+            let kind = match tokens.next() {
+                Some("PK" | "PEQ") => FilterKind::Peaking,
+                Some("LS" | "LSC") => FilterKind::LowShelf,
+                Some("HS" | "HSC") => FilterKind::HighShelf,
+                kind => {
+                    tracing::warn!(line = number, "Skipping unsupported filter type {:?}", kind);
+                    continue;
+                }
+            };
+            let (mut frequency, mut gain, mut q) = (None, None, None);
+            while let Some(token) = tokens.next() {
+                match token {
+                    "Fc" => frequency = Some(parse_number(tokens.next(), number)?),
+                    "Gain" => gain = Some(parse_number(tokens.next(), number)?),
+                    "Q" => q = Some(parse_number(tokens.next(), number)?),
+                    _ => {}
+                }
+            }
+            result.filters.push(ParametricFilter {
+                kind,
+                frequency: frequency.ok_or_else(|| eyre!("Missing frequency in line {}", number))?,
+                gain: gain.ok_or_else(|| eyre!("Missing gain in line {}", number))?,
+                q: q.unwrap_or(DEFAULT_Q)
+            });
+        }
+    }
+    if result.filters.is_empty() {
+        bail!("No supported filters found");
+    }
+    Ok(result)
+}
+
+fn parse_number(token: Option<&str>, line: usize) -> Result<f64> {
+    let token = token.ok_or_else(|| eyre!("Missing value in line {}", line))?;
+    token
+        .parse()
+        .map_err(|_| eyre!("Invalid number \"{}\" in line {}", token, line))
+}
+
+/// Finds the levels whose response is closest to the parametric equalizer, the preamp lowers or raises every band
+pub fn fit_equalizer(parametric_eq: &ParametricEq, equalizer: &dyn Equalizer) -> EqualizerFit {
+    let frequencies = log_frequencies(FIT_POINTS).collect::<Vec<_>>();
+    let target = frequencies
+        .iter()
+        .map(|f| parametric_eq.response(*f))
+        .collect::<Vec<_>>();
+
+    let min = equalizer.base_level() - equalizer.variance();
+    let max = equalizer.base_level() + equalizer.variance();
+    let bands = equalizer.band_layout();
+    let responses = bands
+        .iter()
+        .map(|band| {
+            (min..=max)
+                .map(|level| {
+                    let filter = Biquad::peaking(band.frequency, band.q, equalizer.gain(level));
+                    frequencies
+                        .iter()
+                        .map(|f| filter.response(*f))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut levels = vec![equalizer.base_level(); equalizer.bands() as usize];
+    for (level, band) in levels.iter_mut().zip(bands) {
+        let gain = parametric_eq.response(band.frequency);
+        *level = (equalizer.base_level() as f64 + gain / equalizer.step_size())
+            .round()
+            .clamp(min as f64, max as f64) as u8;
+    }
+    let mut total = vec![0.0; frequencies.len()];
+    for (level, response) in levels.iter().zip(&responses) {
+        add_assign(&mut total, &response[(*level - min) as usize], 1.0);
+    }
+
+    for _ in 0..FIT_ITERATIONS {
+        let mut improved = false;
+        for (level, response) in levels.iter_mut().zip(&responses) {
+            let current = (*level - min) as usize;
+            add_assign(&mut total, &response[current], -1.0);
+            let best = response
+                .iter()
+                .map(|candidate| squared_error(&total, candidate, &target))
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(i, _)| i)
+                .unwrap_or(current);
+            add_assign(&mut total, &response[best], 1.0);
+            if best != current {
+                *level = min + best as u8;
+                improved = true;
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    let errors = total
+        .iter()
+        .zip(&target)
+        .map(|(fitted, target)| fitted - target)
+        .collect::<Vec<_>>();
+    EqualizerFit {
+        levels,
+        rms_error: (errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64).sqrt(),
+        max_error: errors.iter().fold(0.0, |max, e| e.abs().max(max))
+    }
+}
+
+fn add_assign(total: &mut [f64], response: &[f64], factor: f64) {
+    for (t, r) in total.iter_mut().zip(response) {
+        *t += factor * r;
+    }
+}
+
+fn squared_error(total: &[f64], candidate: &[f64], target: &[f64]) -> f64 {
+    total
+        .iter()
+        .zip(candidate)
+        .zip(target)
+        .map(|((t, c), target)| (t + c - target).powi(2))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::EqualizerBand;
+
+    const FILE: &str = "Preamp: -6.2 dB
+Filter 1: ON LSC Fc 105 Hz Gain 5.5 dB Q 0.70
+Filter 2: ON PK Fc 1000 Hz Gain -3.0 dB Q 1.41
+Filter 3: OFF PK Fc 2000 Hz Gain 4.0 dB Q 2.00
+Filter 4: ON HSC Fc 10000 Hz Gain 2.0 dB
+Filter 5: ON BP Fc 3000 Hz Gain 1.0 dB Q 1.00
+";
+
+    struct TestEqualizer;
+
+    impl Equalizer for TestEqualizer {
+        fn base_level(&self) -> u8 {
+            20
+        }
+
+        fn variance(&self) -> u8 {
+            20
+        }
+
+        fn band_layout(&self) -> &[EqualizerBand] {
+            &[
+                EqualizerBand::new(32.0, 1.414),
+                EqualizerBand::new(64.0, 1.414),
+                EqualizerBand::new(125.0, 1.414),
+                EqualizerBand::new(250.0, 1.414),
+                EqualizerBand::new(500.0, 1.414),
+                EqualizerBand::new(1000.0, 1.414),
+                EqualizerBand::new(2000.0, 1.414),
+                EqualizerBand::new(4000.0, 1.414),
+                EqualizerBand::new(8000.0, 1.414),
+                EqualizerBand::new(16000.0, 1.414)
+            ]
+        }
+
+        fn step_size(&self) -> f64 {
+            0.5
+        }
+
+        fn presets(&self) -> &[(&str, &[u8])] {
+            &[]
+        }
+
+        fn set_levels(&self, _levels: &[u8]) {}
+    }
+
+    fn parametric_eq(preamp: f64, filters: Vec<ParametricFilter>) -> ParametricEq {
+        ParametricEq { preamp, filters }
+    }
+
+    #[test]
+    fn parses_preamp_and_enabled_filters() {
+        let parsed = parse_parametric_eq(FILE).unwrap();
+        assert_eq!(parsed.preamp, -6.2);
+        let expected = [
+            (FilterKind::LowShelf, 105.0, 5.5, 0.7),
+            (FilterKind::Peaking, 1000.0, -3.0, 1.41),
+            (FilterKind::HighShelf, 10000.0, 2.0, DEFAULT_Q)
+        ];
+        assert_eq!(parsed.filters.len(), expected.len());
+        for (filter, (kind, frequency, gain, q)) in parsed.filters.iter().zip(expected) {
+            assert_eq!(*filter, ParametricFilter { kind, frequency, gain, q });
+        }
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        assert!(parse_parametric_eq("Preamp: -3 dB").is_err());
+        assert!(parse_parametric_eq("Filter 1: ON PK Fc 100 Hz Q 1.0").is_err());
+        assert!(parse_parametric_eq("Filter 1: ON PK Fc abc Hz Gain 1 dB").is_err());
+        assert!(parse_parametric_eq("Preamp: loud\nFilter 1: ON PK Fc 100 Hz Gain 1 dB").is_err());
+    }
+
+    #[test]
+    fn flat_target_keeps_the_base_levels() {
+        let fit = fit_equalizer(&parametric_eq(0.0, Vec::new()), &TestEqualizer);
+        assert_eq!(fit.levels, vec![20; 10]);
+        assert_eq!(fit.rms_error, 0.0);
+    }
+
+    #[test]
+    fn peak_on_a_band_raises_that_band() {
+        let peak = ParametricFilter {
+            kind: FilterKind::Peaking,
+            frequency: 1000.0,
+            gain: 6.0,
+            q: 1.414
+        };
+        let fit = fit_equalizer(&parametric_eq(0.0, vec![peak]), &TestEqualizer);
+        assert_eq!(fit.levels[5], 32);
+        assert!(fit.rms_error < 0.5, "rms error {}", fit.rms_error);
+    }
+
+    #[test]
+    fn preamp_lowers_every_band() {
+        let fit = fit_equalizer(&parametric_eq(-3.0, Vec::new()), &TestEqualizer);
+        assert!(fit.levels.iter().all(|level| *level < 20), "levels {:?}", fit.levels);
+        let raised = fit_equalizer(&parametric_eq(3.0, Vec::new()), &TestEqualizer);
+        assert!(raised.levels.iter().all(|level| *level > 20), "levels {:?}", raised.levels);
+    }
+}
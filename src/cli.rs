@@ -0,0 +1,765 @@
+use std::io::{ErrorKind, Write};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::config::{Config, EqualizerConfig, EqualizerPreset, HeadsetConfig};
+use crate::devices::{
+    generate_udev_rules, BatteryLevel, BoxedDevice, Device, DeviceManager, DeviceUpdate, SettingDescriptor, SettingId, SettingKind, SettingScope,
+    SettingValue
+};
+use crate::ipc::{self, IpcRequest, IpcResponse, Subscription};
+use crate::util::fill_template;
+use crate::{AppEvent, EventSender};
+
+/// No supported headset could be found or opened
+pub const EXIT_NO_DEVICE: u8 = 3;
+/// The arguments were well-formed but did not match anything (unknown setting, profile or value)
+pub const EXIT_INVALID_ARGUMENT: u8 = 4;
+/// The config file could not be parsed
+pub const EXIT_INVALID_CONFIG: u8 = 5;
+
+/// How often `watch` rereads the config without a running instance
+const WATCH_CONFIG_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait for the headset to accept changed settings
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    after_help = "Exit codes: 0 success, 1 error, 2 invalid usage, 3 no device, 4 invalid argument, 5 invalid config"
+)]
+pub struct Cli {
+    /// Include a fake headset for testing
+    #[arg(long, global = true)]
+    pub dummy_device: bool,
+    /// Print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    pub json: bool,
+    /// The old spelling of the udev-rules command
+    #[arg(long, hide = true)]
+    pub print_udev_rules: bool,
+    #[command(flatten)]
+    pub gui: GuiArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct GuiArgs {
+    /// Start in the tray without opening the window
+    #[arg(long)]
+    pub quiet: bool,
+    /// Exit the application when the window is closed
+    #[arg(long)]
+    pub close_on_quit: bool
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the tray application (default)
+    Gui(GuiArgs),
+    /// Run without window and tray, controlled through the command line and the control socket
+    Daemon,
+    /// Print the state of the headset
+    Status,
+    /// Print one line per state change until killed, for status bars
+    Watch {
+        /// Print plain text instead of JSON. Supports {device}, {connected}, {battery}, {charging}, {game}, {chat} and {profile}
+        #[arg(long)]
+        format: Option<String>
+    },
+    /// List all connected headsets that are supported
+    ListDevices,
+    /// List or select profiles
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Change a setting of the current profile or headset and apply it
+    Set {
+        /// Name of the setting, e.g. side-tone or "Microphone Volume"
+        setting: String,
+        /// New value: a number, on/off, an option name, or an equalizer preset / comma separated levels
+        value: String
+    },
+    /// Print udev rules that grant access to the supported headsets
+    UdevRules,
+    /// Inspect the config file
+    #[command(subcommand)]
+    Config(ConfigCommand)
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// List the profiles of the headset
+    List,
+    /// Select a profile by name or index
+    Select { profile: String }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the location of the config file
+    Path,
+    /// Check that the config file can be parsed
+    Validate
+}
+
+pub fn run(cli: &Cli) -> Result<ExitCode> {
+    match cli.command.as_ref().expect("The gui is started by main") {
+        Command::Gui(_) | Command::Daemon => unreachable!("The gui and the daemon are started by main"),
+        Command::Status => status(cli),
+        Command::Watch { format } => watch(cli, format.as_deref()),
+        Command::ListDevices => list_devices(cli),
+        Command::Profile(ProfileCommand::List) => list_profiles(cli),
+        Command::Profile(ProfileCommand::Select { profile }) => select_profile(cli, profile),
+        Command::Set { setting, value } => set_setting(cli, setting, value),
+        Command::UdevRules => {
+            let rules = generate_udev_rules()?;
+            print(cli.json, &rules, |rules| rules.trim_end().to_string());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Config(ConfigCommand::Path) => {
+            let path = Config::path().display().to_string();
+            print(cli.json, &path, |path| path.clone());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Config(ConfigCommand::Validate) => validate_config(cli)
+    }
+}
+
+fn print<T: Serialize>(json: bool, value: &T, text: impl FnOnce(&T) -> String) {
+    match json {
+        true => println!("{}", serde_json::to_string(value).expect("Failed to serialize output")),
+        false => println!("{}", text(value))
+    }
+}
+
+fn error(cli: &Cli, code: u8, message: String) -> Result<ExitCode> {
+    match cli.json {
+        true => println!("{}", serde_json::json!({ "error": message })),
+        false => eprintln!("{}", message)
+    }
+    Ok(ExitCode::from(code))
+}
+
+fn runtime() -> Result<Runtime> {
+    Ok(Builder::new_multi_thread().enable_all().build()?)
+}
+
+/// Picks the headset the gui would use, without opening it
+fn headset_name(manager: &DeviceManager, config: &Config) -> Option<&'static str> {
+    let devices = manager.supported_devices();
+    config
+        .preferred_device
+        .as_ref()
+        .and_then(|pref| devices.iter().find(|dev| dev.name() == pref))
+        .or_else(|| devices.first())
+        .map(|dev| dev.name())
+}
+
+fn open_device(runtime: &Runtime, manager: &DeviceManager, config: &Config) -> Option<(Receiver<AppEvent>, BoxedDevice)> {
+    // The devices report their updates through the channel, even if nobody is listening
+    let (sender, receiver) = mpsc::channel();
+    runtime
+        .block_on(manager.find_preferred_device(&config.preferred_device, EventSender::Channel(sender)))
+        .map(|device| (receiver, device))
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub device: String,
+    pub connected: bool,
+    pub battery: Option<u8>,
+    pub charging: bool,
+    pub chat_mix: Option<ChatMixStatus>,
+    pub profile: String
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ChatMixStatus {
+    pub game: u8,
+    pub chat: u8
+}
+
+impl Status {
+    pub fn new(device: &dyn Device, config: &mut Config) -> Self {
+        let battery = device.get_battery_status();
+        let connected = device.is_connected();
+        Self {
+            device: device.name().to_string(),
+            connected,
+            battery: match battery {
+                Some(BatteryLevel::Level(level)) => Some(level),
+                _ => None
+            },
+            charging: battery == Some(BatteryLevel::Charging),
+            chat_mix: device
+                .get_chat_mix()
+                .filter(|_| connected)
+                .map(|mix| ChatMixStatus { game: mix.game, chat: mix.chat }),
+            profile: config
+                .get_headset(device.name())
+                .selected_profile()
+                .name
+                .clone()
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Device:    {}", self.device)?;
+        writeln!(f, "Connected: {}", self.connected)?;
+        match (self.battery, self.charging) {
+            (_, true) => writeln!(f, "Battery:   Charging")?,
+            (Some(level), false) => writeln!(f, "Battery:   {}%", level)?,
+            (None, false) => writeln!(f, "Battery:   Unknown")?
+        }
+        if let Some(mix) = &self.chat_mix {
+            writeln!(f, "Chat Mix:  Game {}% / Chat {}%", mix.game, mix.chat)?;
+        }
+        write!(f, "Profile:   {}", self.profile)
+    }
+}
+
+impl Status {
+    pub fn placeholders(&self) -> [(&'static str, String); 7] {
+        [
+            ("device", self.device.clone()),
+            ("connected", self.connected.to_string()),
+            ("battery", self.battery.map_or_else(|| String::from("?"), |level| level.to_string())),
+            ("charging", self.charging.to_string()),
+            ("game", self.chat_mix.map_or_else(String::new, |mix| mix.game.to_string())),
+            ("chat", self.chat_mix.map_or_else(String::new, |mix| mix.chat.to_string())),
+            ("profile", self.profile.clone())
+        ]
+    }
+}
+
+/// A difference between two consecutive states of the same headset
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StatusChange {
+    Connected,
+    Disconnected,
+    BatteryChanged { previous: Option<u8>, current: u8 },
+    ChargingStarted,
+    ChargingFinished,
+    ProfileChanged { previous: String },
+    ChatMixChanged
+}
+
+impl Status {
+    /// Without a previous state, a connected headset counts as newly connected
+    pub fn changes_since(&self, previous: Option<&Status>) -> Vec<StatusChange> {
+        let mut changes = Vec::new();
+        if previous.map_or(false, |p| p.connected) != self.connected {
+            changes.push(match self.connected {
+                true => StatusChange::Connected,
+                false => StatusChange::Disconnected
+            });
+        }
+        let previous_battery = previous.and_then(|p| p.battery);
+        if let Some(current) = self.battery.filter(|level| previous_battery != Some(*level)) {
+            changes.push(StatusChange::BatteryChanged {
+                previous: previous_battery,
+                current
+            });
+        }
+        if previous.map_or(false, |p| p.charging) != self.charging {
+            changes.push(match self.charging {
+                true => StatusChange::ChargingStarted,
+                false => StatusChange::ChargingFinished
+            });
+        }
+        if let Some(previous) = previous {
+            if previous.profile != self.profile {
+                changes.push(StatusChange::ProfileChanged {
+                    previous: previous.profile.clone()
+                });
+            }
+            if self.chat_mix.is_some() && previous.chat_mix != self.chat_mix {
+                changes.push(StatusChange::ChatMixChanged);
+            }
+        }
+        changes
+    }
+}
+
+fn status(cli: &Cli) -> Result<ExitCode> {
+    let runtime = runtime()?;
+    if let Some(code) = forward(cli, &runtime, IpcRequest::Status)? {
+        return Ok(code);
+    }
+    let manager = runtime.block_on(DeviceManager::new(cli.dummy_device))?;
+    let mut config = Config::read()?;
+    let response = match open_device(&runtime, &manager, &config) {
+        Some((_events, device)) => IpcResponse::Status(Status::new(device.as_ref(), &mut config)),
+        None => no_device()
+    };
+    print_response(cli, response)
+}
+
+fn watch(cli: &Cli, format: Option<&str>) -> Result<ExitCode> {
+    let runtime = runtime()?;
+    let result = match runtime.block_on(ipc::subscribe())? {
+        Some(subscription) => watch_running(cli, &runtime, subscription, format),
+        None => watch_device(cli, &runtime, format)
+    };
+    match result {
+        // Whoever reads the output is gone, e.g. `head`
+        Err(err) if err.downcast_ref::<std::io::Error>().map_or(false, |err| err.kind() == ErrorKind::BrokenPipe) => Ok(ExitCode::SUCCESS),
+        result => result
+    }
+}
+
+/// Follows the running instance, which owns the headset
+fn watch_running(cli: &Cli, runtime: &Runtime, mut subscription: Subscription, format: Option<&str>) -> Result<ExitCode> {
+    while let Some(response) = runtime.block_on(subscription.next())? {
+        match response {
+            IpcResponse::Status(status) => print_status(&status, format)?,
+            response => return print_response(cli, response)
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Opens the headset itself and rereads the config regularly, as other commands can change the profile in the meantime
+fn watch_device(cli: &Cli, runtime: &Runtime, format: Option<&str>) -> Result<ExitCode> {
+    let manager = runtime.block_on(DeviceManager::new(cli.dummy_device))?;
+    let mut config = Config::read()?;
+    let Some((events, device)) = open_device(runtime, &manager, &config) else {
+        return print_response(cli, no_device());
+    };
+    let mut last = Status::new(device.as_ref(), &mut config);
+    print_status(&last, format)?;
+    loop {
+        match events.recv_timeout(WATCH_CONFIG_INTERVAL) {
+            Ok(AppEvent::Device(DeviceUpdate::DeviceError(err))) => tracing::error!("The device return an error: {}", err),
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break
+        }
+        config = Config::read()?;
+        let status = Status::new(device.as_ref(), &mut config);
+        if status != last {
+            print_status(&status, format)?;
+            last = status;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_status(status: &Status, format: Option<&str>) -> std::io::Result<()> {
+    let line = match format {
+        Some(format) => fill_template(format, &status.placeholders()),
+        None => serde_json::to_string(status).expect("Failed to serialize output")
+    };
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", line)?;
+    stdout.flush()
+}
+
+/// Hands the request to the running instance, as it owns the headset while it is running
+fn forward(cli: &Cli, runtime: &Runtime, request: IpcRequest) -> Result<Option<ExitCode>> {
+    match runtime.block_on(ipc::send(&request))? {
+        Some(response) => print_response(cli, response).map(Some),
+        None => Ok(None)
+    }
+}
+
+fn print_response(cli: &Cli, response: IpcResponse) -> Result<ExitCode> {
+    match response {
+        IpcResponse::Ok => {}
+        IpcResponse::Status(status) => print(cli.json, &status, |status| status.to_string()),
+        IpcResponse::Profile { index, name } => {
+            let entry = ProfileEntry { index, name, selected: true };
+            print(cli.json, &entry, |entry| format!("Selected profile \"{}\"", entry.name))
+        }
+        IpcResponse::Setting { setting, value } => {
+            let change = SettingEntry { setting, value };
+            print(cli.json, &change, |change| format!("Updated {}", change.setting))
+        }
+        IpcResponse::Profiles(profiles) => print_profiles(cli.json, profiles),
+        IpcResponse::Settings(settings) => print(cli.json, &settings, |settings| {
+            settings
+                .iter()
+                .map(|s| format!("{}: {:?}", s.setting, s.value))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }),
+        IpcResponse::Error { code, message } => return error(cli, code, message)
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn no_device() -> IpcResponse {
+    IpcResponse::Error {
+        code: EXIT_NO_DEVICE,
+        message: String::from("No supported headset found")
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceEntry {
+    name: &'static str,
+    manufacturer: &'static str,
+    product: &'static str,
+    preferred: bool
+}
+
+fn list_devices(cli: &Cli) -> Result<ExitCode> {
+    let runtime = runtime()?;
+    let manager = runtime.block_on(DeviceManager::new(cli.dummy_device))?;
+    let config = Config::read()?;
+    let devices = manager
+        .supported_devices()
+        .iter()
+        .map(|dev| DeviceEntry {
+            name: dev.strings.name,
+            manufacturer: dev.strings.manufacturer,
+            product: dev.strings.product,
+            preferred: config.preferred_device.as_deref() == Some(dev.name())
+        })
+        .collect::<Vec<_>>();
+    print(cli.json, &devices, |devices| {
+        devices
+            .iter()
+            .map(|dev| format!("{}{}", dev.name, if dev.preferred { " (preferred)" } else { "" }))
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    Ok(ExitCode::SUCCESS)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileEntry {
+    pub index: usize,
+    pub name: String,
+    pub selected: bool
+}
+
+pub fn profile_entries(headset: &mut HeadsetConfig) -> Vec<ProfileEntry> {
+    headset.selected_profile();
+    headset
+        .profiles
+        .iter()
+        .enumerate()
+        .map(|(index, profile)| ProfileEntry {
+            index,
+            name: profile.name.clone(),
+            selected: index as u32 == headset.selected_profile_index
+        })
+        .collect()
+}
+
+fn print_profiles(json: bool, profiles: Vec<ProfileEntry>) {
+    print(json, &profiles, |profiles| {
+        profiles
+            .iter()
+            .map(|p| format!("{} {}: {}", if p.selected { "*" } else { " " }, p.index, p.name))
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+}
+
+fn list_profiles(cli: &Cli) -> Result<ExitCode> {
+    let runtime = runtime()?;
+    let manager = runtime.block_on(DeviceManager::new(cli.dummy_device))?;
+    let mut config = Config::read()?;
+    let Some(name) = headset_name(&manager, &config) else {
+        return print_response(cli, no_device());
+    };
+    print_profiles(cli.json, profile_entries(config.get_headset(name)));
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Finds a profile by its name or its index
+pub fn find_profile(headset: &mut HeadsetConfig, profile: &str) -> Result<usize, IpcResponse> {
+    headset.selected_profile();
+    headset
+        .profiles
+        .iter()
+        .position(|p| p.name.eq_ignore_ascii_case(profile))
+        .or_else(|| profile.parse::<usize>().ok().filter(|i| *i < headset.profiles.len()))
+        .ok_or_else(|| IpcResponse::Error {
+            code: EXIT_INVALID_ARGUMENT,
+            message: format!("Unknown profile \"{}\"", profile)
+        })
+}
+
+fn select_profile(cli: &Cli, profile: &str) -> Result<ExitCode> {
+    let runtime = runtime()?;
+    if let Some(code) = forward(cli, &runtime, IpcRequest::SelectProfile(profile.to_string()))? {
+        return Ok(code);
+    }
+    let manager = runtime.block_on(DeviceManager::new(cli.dummy_device))?;
+    let mut config = Config::load()?;
+    let Some(name) = headset_name(&manager, &config) else {
+        return print_response(cli, no_device());
+    };
+    let headset = config.get_headset(name);
+    let index = match find_profile(headset, profile) {
+        Ok(index) => index,
+        Err(response) => return print_response(cli, response)
+    };
+    headset.selected_profile_index = index as u32;
+    let name = headset.profiles[index].name.clone();
+    config.save()?;
+    apply_settings(&runtime, &manager, &mut config, |setting| setting.scope == SettingScope::Profile);
+    print_response(cli, IpcResponse::Profile { index, name })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingEntry {
+    pub setting: String,
+    pub value: SettingValue
+}
+
+/// The stored values of every setting the device supports
+pub fn setting_entries(headset: &mut HeadsetConfig, device: &dyn Device) -> Vec<SettingEntry> {
+    device
+        .settings()
+        .iter()
+        .map(|descriptor| SettingEntry {
+            setting: descriptor.label.to_string(),
+            value: headset.setting(descriptor).clone()
+        })
+        .collect()
+}
+
+/// A short human-readable form of the value, e.g. the name of an equalizer preset
+pub fn describe_value(descriptor: &SettingDescriptor, value: &SettingValue, presets: &[EqualizerPreset]) -> String {
+    match (value, &descriptor.kind) {
+        (SettingValue::Choice(i), SettingKind::Enum { options }) => options
+            .get(*i as usize)
+            .map_or_else(|| i.to_string(), |option| option.to_string()),
+        (SettingValue::Level(level), _) | (SettingValue::Choice(level), _) => level.to_string(),
+        (SettingValue::Toggle(enabled), _) => String::from(if *enabled { "On" } else { "Off" }),
+        (SettingValue::Bands(EqualizerConfig::Preset(name)), _) => name.clone(),
+        (SettingValue::Bands(EqualizerConfig::UserPreset(id)), _) => presets
+            .iter()
+            .find(|preset| preset.id == *id)
+            .map_or_else(|| String::from("Unknown Preset"), |preset| preset.name.clone()),
+        (SettingValue::Bands(EqualizerConfig::Custom(_)), _) => String::from("Custom")
+    }
+}
+
+/// Parses the value and stores it in the config. The caller is responsible for applying it to the device.
+pub fn update_setting(headset: &mut HeadsetConfig, device: &dyn Device, setting: &str, value: &str) -> Result<SettingDescriptor, IpcResponse> {
+    let settings = device.settings();
+    let Some(descriptor) = find_setting(&settings, setting) else {
+        let names = settings.iter().map(|s| s.label).collect::<Vec<_>>().join(", ");
+        return Err(IpcResponse::Error {
+            code: EXIT_INVALID_ARGUMENT,
+            message: format!("Unknown setting \"{}\", expected one of: {}", setting, names)
+        });
+    };
+    store_setting(headset, device, descriptor, value)
+}
+
+/// Like [update_setting] for a setting that is already known
+pub fn update_setting_by_id(headset: &mut HeadsetConfig, device: &dyn Device, id: SettingId, value: &str) -> Result<SettingDescriptor, IpcResponse> {
+    let settings = device.settings();
+    let Some(descriptor) = settings.iter().find(|s| s.id == id) else {
+        return Err(IpcResponse::Error {
+            code: EXIT_INVALID_ARGUMENT,
+            message: format!("{} does not support {:?}", device.name(), id)
+        });
+    };
+    store_setting(headset, device, descriptor, value)
+}
+
+fn store_setting(
+    headset: &mut HeadsetConfig, device: &dyn Device, descriptor: &SettingDescriptor, value: &str
+) -> Result<SettingDescriptor, IpcResponse> {
+    let parsed = parse_value(descriptor, value, &headset.equalizer_presets, device).map_err(|message| IpcResponse::Error {
+        code: EXIT_INVALID_ARGUMENT,
+        message
+    })?;
+    *headset.setting(descriptor) = parsed;
+    Ok(descriptor.clone())
+}
+
+fn set_setting(cli: &Cli, setting: &str, value: &str) -> Result<ExitCode> {
+    let runtime = runtime()?;
+    let request = IpcRequest::SetSetting {
+        setting: setting.to_string(),
+        value: value.to_string()
+    };
+    if let Some(code) = forward(cli, &runtime, request)? {
+        return Ok(code);
+    }
+    let manager = runtime.block_on(DeviceManager::new(cli.dummy_device))?;
+    let mut config = Config::load()?;
+    let Some((_events, device)) = open_device(&runtime, &manager, &config) else {
+        return print_response(cli, no_device());
+    };
+    let headset = config.get_headset(device.name());
+    let descriptor = match update_setting(headset, device.as_ref(), setting, value) {
+        Ok(descriptor) => descriptor,
+        Err(response) => return print_response(cli, response)
+    };
+    let resolved = headset.resolved_setting(&descriptor);
+    let response = IpcResponse::Setting {
+        setting: descriptor.label.to_string(),
+        value: headset.setting(&descriptor).clone()
+    };
+    config.save()?;
+    if device.is_connected() {
+        device.apply_setting(descriptor.id, &resolved);
+        flush(&runtime, device.as_ref());
+    }
+    print_response(cli, response)
+}
+
+fn apply_settings(runtime: &Runtime, manager: &DeviceManager, config: &mut Config, filter: impl Fn(&SettingDescriptor) -> bool) {
+    match open_device(runtime, manager, config) {
+        Some((_events, device)) if device.is_connected() => {
+            let headset = config.get_headset(device.name());
+            for setting in device.settings().iter().filter(|s| filter(s)) {
+                let value = headset.resolved_setting(setting);
+                device.apply_setting(setting.id, &value);
+            }
+            flush(runtime, device.as_ref());
+        }
+        _ => tracing::info!("Headset is not connected, the changes will be applied on the next connection")
+    }
+}
+
+/// Waits until the device sent the queued requests, the runtime and with it the device tasks shut down afterwards
+fn flush(runtime: &Runtime, device: &dyn Device) {
+    if runtime
+        .block_on(tokio::time::timeout(FLUSH_TIMEOUT, device.flush()))
+        .is_err()
+    {
+        tracing::warn!("The headset did not accept the changes within {:?}", FLUSH_TIMEOUT);
+    }
+}
+
+/// Changes a single equalizer band, starting from the levels of the current preset. The caller is responsible for applying it.
+pub fn update_equalizer_band(headset: &mut HeadsetConfig, device: &dyn Device, band: usize, level: u8) -> Result<SettingDescriptor, IpcResponse> {
+    let invalid = |message: String| IpcResponse::Error {
+        code: EXIT_INVALID_ARGUMENT,
+        message
+    };
+    let settings = device.settings();
+    let descriptor = settings.iter().find(|s| s.id == SettingId::Equalizer);
+    let (Some(descriptor), Some(equalizer)) = (descriptor, device.get_equalizer()) else {
+        return Err(invalid(String::from("The headset has no equalizer")));
+    };
+    let default_levels = || vec![equalizer.base_level(); equalizer.bands() as usize];
+    let mut levels = match headset.resolved_setting(descriptor) {
+        SettingValue::Bands(EqualizerConfig::Custom(levels)) => levels,
+        SettingValue::Bands(EqualizerConfig::Preset(name)) => equalizer
+            .presets()
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map_or_else(default_levels, |(_, levels)| levels.to_vec()),
+        _ => default_levels()
+    };
+    let Some(current) = levels.get_mut(band) else {
+        return Err(invalid(format!("Expected a band between 1 and {}", equalizer.bands())));
+    };
+    *current = level;
+    let value = SettingValue::Bands(EqualizerConfig::Custom(levels));
+    if !descriptor.accepts(&value, &headset.equalizer_presets) {
+        return Err(invalid(format!("{} is out of range for {}", level, descriptor.label)));
+    }
+    *headset.setting(descriptor) = value;
+    Ok(descriptor.clone())
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn find_setting<'a>(settings: &'a [SettingDescriptor], name: &str) -> Option<&'a SettingDescriptor> {
+    let name = normalize(name);
+    settings
+        .iter()
+        .find(|s| normalize(s.label) == name || normalize(&format!("{:?}", s.id)) == name)
+}
+
+fn parse_value(setting: &SettingDescriptor, value: &str, presets: &[EqualizerPreset], device: &dyn Device) -> Result<SettingValue, String> {
+    let parsed = match &setting.kind {
+        SettingKind::Slider { min, max } => value
+            .parse::<u8>()
+            .ok()
+            .filter(|v| (*min..=*max).contains(v))
+            .map(SettingValue::Level)
+            .ok_or_else(|| format!("Expected a number between {} and {}", min, max))?,
+        SettingKind::Toggle => match value.to_lowercase().as_str() {
+            "on" | "true" | "yes" | "1" => SettingValue::Toggle(true),
+            "off" | "false" | "no" | "0" => SettingValue::Toggle(false),
+            _ => return Err(String::from("Expected on or off"))
+        },
+        SettingKind::Enum { options } => options
+            .iter()
+            .position(|option| normalize(option) == normalize(value))
+            .or_else(|| value.parse::<usize>().ok().filter(|i| *i < options.len()))
+            .map(|i| SettingValue::Choice(i as u8))
+            .ok_or_else(|| format!("Expected one of: {}", options.join(", ")))?,
+        SettingKind::Bands { count, .. } => {
+            let builtin = device
+                .get_equalizer()
+                .and_then(|eq| eq.presets().iter().find(|(name, _)| normalize(name) == normalize(value)))
+                .map(|(name, _)| EqualizerConfig::Preset(name.to_string()));
+            let user = presets
+                .iter()
+                .find(|preset| normalize(&preset.name) == normalize(value))
+                .map(|preset| EqualizerConfig::UserPreset(preset.id));
+            match builtin.or(user) {
+                Some(config) => SettingValue::Bands(config),
+                None => value
+                    .split(',')
+                    .map(|level| level.trim().parse::<u8>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|levels| SettingValue::Bands(EqualizerConfig::Custom(levels)))
+                    .map_err(|_| format!("Expected a preset name or {} comma separated levels", count))?
+            }
+        }
+    };
+    match setting.accepts(&parsed, presets) {
+        true => Ok(parsed),
+        false => Err(format!("{} is out of range for {}", value, setting.label))
+    }
+}
+
+fn validate_config(cli: &Cli) -> Result<ExitCode> {
+    #[derive(Serialize)]
+    struct Validation {
+        path: String,
+        valid: bool,
+        error: Option<String>
+    }
+    let path = Config::path();
+    let error = match path.exists() {
+        true => ron::from_str::<Config>(&std::fs::read_to_string(path)?)
+            .err()
+            .map(|err| err.to_string()),
+        false => None
+    };
+    let validation = Validation {
+        path: path.display().to_string(),
+        valid: error.is_none(),
+        error
+    };
+    print(cli.json, &validation, |v| match &v.error {
+        Some(err) => format!("{} is invalid: {}", v.path, err),
+        None if !path.exists() => format!("{} does not exist, the defaults will be used", v.path),
+        None => format!("{} is valid", v.path)
+    });
+    Ok(match validation.valid {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::from(EXIT_INVALID_CONFIG)
+    })
+}
@@ -1,14 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::Write;
+use std::io::{ErrorKind, Write};
 use std::path::{Path, PathBuf};
 
 use color_eyre::Result;
 use directories_next::BaseDirs;
 use once_cell::sync::Lazy;
 use ron::ser::{to_string_pretty, PrettyConfig};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::devices::{SettingDescriptor, SettingId, SettingKind, SettingScope, SettingValue};
+use crate::dsp::ParametricFilter;
+use crate::hooks::Hook;
 use crate::util::EscapeStripper;
 
 #[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -27,10 +31,50 @@ pub enum OsAudio {
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum EqualizerConfig {
-    Preset(u32),
+    /// Older versions stored the position of the preset, which [SettingMap::value] replaces with the name
+    Preset(#[serde(deserialize_with = "preset_name")] String),
+    UserPreset(u32),
     Custom(Vec<u8>)
 }
 
+fn preset_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PresetName {
+        Name(String),
+        Index(u32)
+    }
+    Ok(match PresetName::deserialize(deserializer)? {
+        PresetName::Name(name) => name,
+        PresetName::Index(index) => index.to_string()
+    })
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EqualizerPreset {
+    pub id: u32,
+    pub name: String,
+    pub levels: Vec<u8>
+}
+
+/// Adds the preset under the first free name of the form `<name>`, `<name> 2`, ... and returns its id
+pub fn add_equalizer_preset(presets: &mut Vec<EqualizerPreset>, name: &str, levels: Vec<u8>) -> u32 {
+    let id = presets
+        .iter()
+        .map(|p| p.id + 1)
+        .max()
+        .unwrap_or(0);
+    let name = (1..)
+        .map(|i| match i {
+            1 => name.to_string(),
+            i => format!("{} {}", name, i)
+        })
+        .find(|candidate| presets.iter().all(|p| p.name != *candidate))
+        .expect("There is always a free name");
+    presets.push(EqualizerPreset { id, name, levels });
+    id
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum CallAction {
     Nothing,
@@ -38,23 +82,144 @@ pub enum CallAction {
     Mute
 }
 
+impl CallAction {
+    pub const ACTIONS: [Self; 3] = [Self::Nothing, Self::ReduceVolume, Self::Mute];
+    pub const NAMES: &'static [&'static str] = &["Nothing", "Reduce Volume", "Mute"];
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum NoiseCancellingMode {
+    Off,
+    Transparency,
+    On
+}
+
+impl NoiseCancellingMode {
+    pub const MODES: [Self; 3] = [Self::Off, Self::Transparency, Self::On];
+    pub const NAMES: &'static [&'static str] = &["Off", "Transparency", "On"];
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SettingMap(BTreeMap<SettingId, SettingValue>);
+
+impl SettingMap {
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut SettingValue> {
+        self.0.values_mut()
+    }
+
+    pub fn value(&mut self, descriptor: &SettingDescriptor, user_presets: &[EqualizerPreset]) -> &mut SettingValue {
+        let value = self
+            .0
+            .entry(descriptor.id)
+            .or_insert_with(|| descriptor.default.clone());
+        if let (SettingKind::Bands { presets, .. }, SettingValue::Bands(EqualizerConfig::Preset(name))) = (&descriptor.kind, &mut *value) {
+            let legacy = name
+                .parse::<usize>()
+                .ok()
+                .filter(|_| !presets.contains(name))
+                .and_then(|index| presets.get(index));
+            if let Some(preset) = legacy {
+                tracing::debug!("Replacing the preset index {} with {}", name, preset);
+                *name = preset.clone();
+            }
+        }
+        if !descriptor.accepts(value, user_presets) {
+            tracing::warn!("Stored value {:?} is invalid for {:?}, resetting it", value, descriptor.id);
+            *value = descriptor.default.clone();
+        }
+        value
+    }
+
+    /// Moves a value of the config format before the setting descriptors into the map, unless it already has one
+    fn migrate(&mut self, id: SettingId, value: Option<SettingValue>) {
+        if let Some(value) = value {
+            self.0.entry(id).or_insert(value);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Profile {
     pub name: String,
-    pub side_tone: u8,
-    pub volume_limiter: bool,
-    pub microphone_volume: u8,
-    pub equalizer: EqualizerConfig
+    #[serde(default)]
+    pub settings: SettingMap,
+    #[serde(default)]
+    pub software_equalizer: SoftwareEqualizer,
+    #[serde(default)]
+    pub microphone_chain: MicrophoneChain,
+    // The fields of the old config format, moved into `settings` by `migrate`
+    #[serde(default, skip_serializing)]
+    side_tone: Option<u8>,
+    #[serde(default, skip_serializing)]
+    volume_limiter: Option<bool>,
+    #[serde(default, skip_serializing)]
+    microphone_volume: Option<u8>,
+    #[serde(default, skip_serializing)]
+    equalizer: Option<EqualizerConfig>
 }
 
 impl Profile {
     pub(crate) fn new(name: String) -> Self {
         Self {
             name,
-            side_tone: 0,
-            volume_limiter: true,
-            microphone_volume: 0,
-            equalizer: EqualizerConfig::Preset(0)
+            settings: SettingMap::default(),
+            software_equalizer: SoftwareEqualizer::default(),
+            microphone_chain: MicrophoneChain::default(),
+            side_tone: None,
+            volume_limiter: None,
+            microphone_volume: None,
+            equalizer: None
+        }
+    }
+
+    fn migrate(&mut self) {
+        self.settings
+            .migrate(SettingId::SideTone, self.side_tone.take().map(SettingValue::Level));
+        self.settings
+            .migrate(SettingId::VolumeLimiter, self.volume_limiter.take().map(SettingValue::Toggle));
+        self.settings
+            .migrate(SettingId::MicrophoneVolume, self.microphone_volume.take().map(SettingValue::Level));
+        self.settings
+            .migrate(SettingId::Equalizer, self.equalizer.take().map(SettingValue::Bands));
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoftwareEqualizer {
+    pub enabled: bool,
+    pub preamp: f64,
+    pub filters: Vec<ParametricFilter>
+}
+
+impl SoftwareEqualizer {
+    pub fn is_active(&self) -> bool {
+        self.enabled && (self.preamp != 0.0 || !self.filters.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MicrophoneChain {
+    pub enabled: bool,
+    pub noise_gate: bool,
+    pub gate_threshold: f64,
+    pub compressor: bool,
+    pub compressor_threshold: f64,
+    pub compressor_ratio: f64,
+    pub gain: f64
+}
+
+impl Default for MicrophoneChain {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            noise_gate: true,
+            gate_threshold: -50.0,
+            compressor: true,
+            compressor_threshold: -20.0,
+            compressor_ratio: 4.0,
+            gain: 0.0
         }
     }
 }
@@ -62,33 +227,469 @@ impl Profile {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HeadsetConfig {
     pub os_audio: OsAudio,
-    pub mic_light: u8,
-    pub bluetooth_call: CallAction,
-    pub auto_enable_bluetooth: bool,
-    pub inactive_time: u8,
+    #[serde(default)]
+    pub settings: SettingMap,
+    #[serde(default)]
+    pub equalizer_presets: Vec<EqualizerPreset>,
     pub selected_profile_index: u32,
-    pub profiles: Vec<Profile>
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub auto_profiles: AutoProfileConfig,
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+    // The fields of the old config format, moved into `settings` by `migrate`
+    #[serde(default, skip_serializing)]
+    mic_light: Option<u8>,
+    #[serde(default, skip_serializing)]
+    bluetooth_call: Option<CallAction>,
+    #[serde(default, skip_serializing)]
+    auto_enable_bluetooth: Option<bool>,
+    #[serde(default, skip_serializing)]
+    inactive_time: Option<u8>
 }
 
 impl Default for HeadsetConfig {
     fn default() -> Self {
         Self {
             os_audio: Default::default(),
-            mic_light: 0,
-            bluetooth_call: CallAction::Nothing,
-            auto_enable_bluetooth: false,
-            inactive_time: 30,
+            settings: SettingMap::default(),
+            equalizer_presets: Vec::new(),
             selected_profile_index: 0,
-            profiles: vec![Profile::new(String::from("Default"))]
+            profiles: vec![Profile::new(String::from("Default"))],
+            auto_profiles: AutoProfileConfig::default(),
+            schedules: Vec::new(),
+            mic_light: None,
+            bluetooth_call: None,
+            auto_enable_bluetooth: None,
+            inactive_time: None
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ProcessRule {
+    /// The executable name, compared case-insensitively
+    pub process: String,
+    pub profile: String
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoProfileConfig {
+    pub enabled: bool,
+    /// The first rule with a running process wins
+    pub rules: Vec<ProcessRule>,
+    /// Selected when no rule matches, keeps the current profile if empty
+    pub fallback: Option<String>,
+    /// Set when the profile was changed by hand, stops the switching until cleared
+    pub pinned: bool
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Day {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday
+}
+
+impl Day {
+    pub const DAYS: [Day; 7] = [
+        Day::Monday,
+        Day::Tuesday,
+        Day::Wednesday,
+        Day::Thursday,
+        Day::Friday,
+        Day::Saturday,
+        Day::Sunday
+    ];
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct TimeOfDay {
+    pub hour: u8,
+    pub minute: u8
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledSetting {
+    pub setting: SettingId,
+    /// The value as accepted by the `set` command
+    pub value: String
+}
+
+/// Switches the profile and changes settings every week at the given local time
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schedule {
+    pub name: String,
+    pub enabled: bool,
+    /// Every day if empty
+    pub days: Vec<Day>,
+    pub time: TimeOfDay,
+    pub profile: Option<String>,
+    pub settings: Vec<ScheduledSetting>
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            name: String::from("New Schedule"),
+            enabled: true,
+            days: Vec::new(),
+            time: TimeOfDay::default(),
+            profile: None,
+            settings: Vec::new()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// The state topics are published below `<topic_prefix>/`
+    pub topic_prefix: String,
+    /// Set to `None` to skip the Home Assistant discovery messages
+    pub discovery_prefix: Option<String>
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::from("localhost"),
+            port: 1883,
+            username: None,
+            password: None,
+            topic_prefix: String::from("headset-controller"),
+            discovery_prefix: Some(String::from("homeassistant"))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub enabled: bool,
+    /// The server only listens on the loopback interface
+    pub port: u16,
+    /// Generated on the first start if left empty
+    pub token: String
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7878,
+            token: String::new()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscConfig {
+    pub enabled: bool,
+    /// Use `0.0.0.0:<port>` to accept messages from other machines
+    pub listen: String,
+    /// Where the state is mirrored to, e.g. `192.168.1.20:9001`
+    pub send_to: Option<String>
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: String::from("127.0.0.1:9000"),
+            send_to: None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum NotificationEvent {
+    Connected,
+    Disconnected,
+    LowBattery,
+    ChargingStarted,
+    /// Charging stopped with a full battery
+    ChargingFinished,
+    /// Charging stopped before the battery was full, e.g. because the cable was unplugged
+    ChargingStopped,
+    ProfileChanged
+}
+
+impl NotificationEvent {
+    pub const EVENTS: [NotificationEvent; 7] = [
+        NotificationEvent::Connected,
+        NotificationEvent::Disconnected,
+        NotificationEvent::LowBattery,
+        NotificationEvent::ChargingStarted,
+        NotificationEvent::ChargingFinished,
+        NotificationEvent::ChargingStopped,
+        NotificationEvent::ProfileChanged
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NotificationEvent::Connected => "Connected",
+            NotificationEvent::Disconnected => "Disconnected",
+            NotificationEvent::LowBattery => "Low Battery",
+            NotificationEvent::ChargingStarted => "Charging Started",
+            NotificationEvent::ChargingFinished => "Charging Finished",
+            NotificationEvent::ChargingStopped => "Charging Stopped",
+            NotificationEvent::ProfileChanged => "Profile Changed"
+        }
+    }
+
+    /// The value of `HEADSET_NOTIFICATION`
+    pub fn name(self) -> &'static str {
+        match self {
+            NotificationEvent::Connected => "connected",
+            NotificationEvent::Disconnected => "disconnected",
+            NotificationEvent::LowBattery => "low_battery",
+            NotificationEvent::ChargingStarted => "charging_started",
+            NotificationEvent::ChargingFinished => "charging_finished",
+            NotificationEvent::ChargingStopped => "charging_stopped",
+            NotificationEvent::ProfileChanged => "profile_changed"
+        }
+    }
+
+    /// Used when the title of the event is empty
+    pub fn default_title(self) -> &'static str {
+        match self {
+            NotificationEvent::ProfileChanged => "Profile: {profile}",
+            _ => "{device}"
+        }
+    }
+
+    /// Used when the template of the event is empty
+    pub fn default_template(self) -> &'static str {
+        match self {
+            NotificationEvent::Connected => "Connected (Battery: {battery}%)",
+            NotificationEvent::Disconnected => "Disconnected (Battery: {battery}%)",
+            NotificationEvent::LowBattery => "Battery low ({battery}%)",
+            NotificationEvent::ChargingStarted => "Charging started",
+            NotificationEvent::ChargingFinished => "Charging finished",
+            NotificationEvent::ChargingStopped => "Charging stopped ({battery}%)",
+            NotificationEvent::ProfileChanged => "{settings}"
         }
     }
 }
 
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum NotificationUrgency {
+    Low,
+    #[default]
+    Normal,
+    Critical
+}
+
+impl NotificationUrgency {
+    pub const URGENCIES: [NotificationUrgency; 3] = [NotificationUrgency::Low, NotificationUrgency::Normal, NotificationUrgency::Critical];
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum NotificationTarget {
+    #[default]
+    Desktop,
+    Log,
+    /// Runs the command like a hook with `HEADSET_TITLE` and `HEADSET_BODY` set
+    Hook(String)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub enabled: bool,
+    pub duration_ms: u32,
+    pub urgency: NotificationUrgency,
+    /// The default title of the event if empty
+    pub title: String,
+    /// Supports the placeholders of `watch --format`, the default text of the event if empty
+    pub template: String,
+    pub target: NotificationTarget
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            duration_ms: 2000,
+            urgency: NotificationUrgency::Normal,
+            title: String::new(),
+            template: String::new(),
+            target: NotificationTarget::Desktop
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Every level in percent gets its own alert when the battery drops to it
+    pub battery_thresholds: Vec<u8>,
+    /// Offered as a button of the low battery notification if the headset has a profile with this name
+    pub low_power_profile: String,
+    pub connected: NotificationSettings,
+    pub disconnected: NotificationSettings,
+    pub low_battery: NotificationSettings,
+    pub charging_started: NotificationSettings,
+    pub charging_finished: NotificationSettings,
+    pub charging_stopped: NotificationSettings,
+    /// Shows the new profile with its equalizer and side tone, off by default
+    pub profile_changed: NotificationSettings
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            battery_thresholds: vec![30, 15, 5],
+            low_power_profile: String::from("Low Power"),
+            connected: NotificationSettings::default(),
+            disconnected: NotificationSettings::default(),
+            low_battery: NotificationSettings {
+                urgency: NotificationUrgency::Critical,
+                ..Default::default()
+            },
+            charging_started: NotificationSettings::default(),
+            charging_finished: NotificationSettings::default(),
+            charging_stopped: NotificationSettings::default(),
+            profile_changed: NotificationSettings {
+                enabled: false,
+                duration_ms: 1500,
+                urgency: NotificationUrgency::Low,
+                ..Default::default()
+            }
+        }
+    }
+}
+
+impl NotificationConfig {
+    pub fn event(&self, event: NotificationEvent) -> &NotificationSettings {
+        match event {
+            NotificationEvent::Connected => &self.connected,
+            NotificationEvent::Disconnected => &self.disconnected,
+            NotificationEvent::LowBattery => &self.low_battery,
+            NotificationEvent::ChargingStarted => &self.charging_started,
+            NotificationEvent::ChargingFinished => &self.charging_finished,
+            NotificationEvent::ChargingStopped => &self.charging_stopped,
+            NotificationEvent::ProfileChanged => &self.profile_changed
+        }
+    }
+
+    pub fn event_mut(&mut self, event: NotificationEvent) -> &mut NotificationSettings {
+        match event {
+            NotificationEvent::Connected => &mut self.connected,
+            NotificationEvent::Disconnected => &mut self.disconnected,
+            NotificationEvent::LowBattery => &mut self.low_battery,
+            NotificationEvent::ChargingStarted => &mut self.charging_started,
+            NotificationEvent::ChargingFinished => &mut self.charging_finished,
+            NotificationEvent::ChargingStopped => &mut self.charging_stopped,
+            NotificationEvent::ProfileChanged => &mut self.profile_changed
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MidiControl {
+    ControlChange { channel: u8, controller: u32 },
+    Note { channel: u8, note: u8 }
+}
+
+impl Display for MidiControl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MidiControl::ControlChange { channel, controller } => write!(f, "CC {} (Ch. {})", controller, channel + 1),
+            MidiControl::Note { channel, note } => write!(f, "Note {} (Ch. {})", note, channel + 1)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MidiTarget {
+    Setting(SettingId),
+    /// Starts at 0
+    EqualizerBand(u8),
+    /// The name of the profile, so the binding survives reordering
+    Profile(String)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub control: MidiControl,
+    pub target: MidiTarget
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiConfig {
+    pub enabled: bool,
+    pub mappings: Vec<MidiMapping>,
+    /// The target that gets bound to the next control that is moved
+    #[serde(skip)]
+    pub learn: Option<MidiTarget>
+}
+
+impl MidiConfig {
+    pub fn target(&self, control: MidiControl) -> Option<MidiTarget> {
+        self.mappings
+            .iter()
+            .find(|m| m.control == control)
+            .map(|m| m.target.clone())
+    }
+
+    pub fn control(&self, target: &MidiTarget) -> Option<MidiControl> {
+        self.mappings
+            .iter()
+            .find(|m| m.target == *target)
+            .map(|m| m.control)
+    }
+
+    /// Every control and every target can only be part of one mapping
+    pub fn assign(&mut self, control: MidiControl, target: MidiTarget) {
+        self.mappings
+            .retain(|m| m.control != control && m.target != target);
+        self.mappings.push(MidiMapping { control, target });
+    }
+
+    pub fn clear(&mut self, target: &MidiTarget) {
+        self.mappings.retain(|m| m.target != *target);
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     headsets: HashMap<String, HeadsetConfig>,
     pub auto_apply_changes: bool,
-    pub preferred_device: Option<String>
+    pub preferred_device: Option<String>,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub osc: OscConfig,
+    #[serde(default)]
+    pub midi: MidiConfig,
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+    #[serde(default)]
+    pub notifications: NotificationConfig
 }
 
 impl Default for Config {
@@ -96,7 +697,13 @@ impl Default for Config {
         Self {
             headsets: HashMap::new(),
             auto_apply_changes: true,
-            preferred_device: None
+            preferred_device: None,
+            mqtt: MqttConfig::default(),
+            http: HttpConfig::default(),
+            osc: OscConfig::default(),
+            midi: MidiConfig::default(),
+            hooks: Vec::new(),
+            notifications: NotificationConfig::default()
         }
     }
 }
@@ -104,6 +711,17 @@ impl Default for Config {
 static BASE_PATH: Lazy<BaseDirs> = Lazy::new(|| BaseDirs::new().expect("can not get directories"));
 static CONFIG_PATH: Lazy<PathBuf> = Lazy::new(|| BASE_PATH.config_dir().join("HeadsetController.ron"));
 static LOG_PATH: Lazy<PathBuf> = Lazy::new(|| BASE_PATH.config_dir().join("HeadsetController.log"));
+static SCRIPT_PATH: Lazy<PathBuf> = Lazy::new(|| BASE_PATH.config_dir().join("HeadsetController.rhai"));
+
+/// The private directory of the user for sockets and generated files
+pub fn runtime_dir() -> std::io::Result<PathBuf> {
+    match BASE_PATH.runtime_dir() {
+        Some(dir) => Ok(dir.to_path_buf()),
+        // The temporary directory is already private to the user on macOS
+        None if cfg!(target_os = "macos") => Ok(std::env::temp_dir()),
+        None => Err(std::io::Error::new(ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))
+    }
+}
 
 pub fn log_file() -> impl Write {
     let file = File::create(LOG_PATH.as_path()).expect("Can not open file");
@@ -115,18 +733,31 @@ impl Config {
         CONFIG_PATH.as_path()
     }
 
+    /// The automation script, see [crate::scripts::Scripts]
+    pub fn script_path() -> &'static Path {
+        SCRIPT_PATH.as_path()
+    }
+
+    /// Like [Config::read], but also creates the config file if it does not exist yet
     pub fn load() -> Result<Self> {
+        let config = Self::read()?;
+        if !Self::path().exists() {
+            config.save()?;
+        }
+        Ok(config)
+    }
+
+    /// Reads the config file without touching it, a missing file yields the default config
+    pub fn read() -> Result<Self> {
         let config: Self = match Self::path().exists() {
             true => {
                 let file = std::fs::read_to_string(Self::path())?;
 
-                ron::from_str(&file)?
-            }
-            false => {
-                let conf = Self::default();
-                conf.save()?;
-                conf
+                let mut config: Self = ron::from_str(&file)?;
+                config.headsets.values_mut().for_each(HeadsetConfig::migrate);
+                config
             }
+            false => Self::default()
         };
         Ok(config)
     }
@@ -148,6 +779,21 @@ impl Config {
 }
 
 impl HeadsetConfig {
+    fn migrate(&mut self) {
+        let call_action = self.bluetooth_call.take().map(|action| {
+            let index = CallAction::ACTIONS.iter().position(|a| *a == action).unwrap_or(0);
+            SettingValue::Choice(index as u8)
+        });
+        self.settings
+            .migrate(SettingId::MicrophoneLight, self.mic_light.take().map(SettingValue::Level));
+        self.settings.migrate(SettingId::BluetoothCall, call_action);
+        self.settings
+            .migrate(SettingId::AutoBluetooth, self.auto_enable_bluetooth.take().map(SettingValue::Toggle));
+        self.settings
+            .migrate(SettingId::InactiveTime, self.inactive_time.take().map(SettingValue::Level));
+        self.profiles.iter_mut().for_each(Profile::migrate);
+    }
+
     pub fn selected_profile(&mut self) -> &mut Profile {
         if self.profiles.is_empty() {
             tracing::debug!("No profile creating a new one");
@@ -159,9 +805,119 @@ impl HeadsetConfig {
         }
         &mut self.profiles[self.selected_profile_index as usize]
     }
+
+    pub fn selected_profile_and_presets(&mut self) -> (&mut Profile, &mut Vec<EqualizerPreset>) {
+        self.selected_profile();
+        (&mut self.profiles[self.selected_profile_index as usize], &mut self.equalizer_presets)
+    }
+
+    pub fn setting(&mut self, descriptor: &SettingDescriptor) -> &mut SettingValue {
+        match descriptor.scope {
+            SettingScope::Profile => {
+                let (profile, presets) = self.selected_profile_and_presets();
+                profile.settings.value(descriptor, presets)
+            }
+            SettingScope::Headset => self.settings.value(descriptor, &self.equalizer_presets)
+        }
+    }
+
+    /// The value to send to the device, with user presets replaced by their levels. Unknown presets fall back to the default.
+    pub fn resolved_setting(&mut self, descriptor: &SettingDescriptor) -> SettingValue {
+        match self.setting(descriptor).clone() {
+            SettingValue::Bands(EqualizerConfig::UserPreset(id)) => match self.equalizer_presets.iter().find(|p| p.id == id) {
+                Some(preset) => SettingValue::Bands(EqualizerConfig::Custom(preset.levels.clone())),
+                None => {
+                    tracing::warn!(id, "Unknown equalizer preset, using the default instead");
+                    descriptor.default.clone()
+                }
+            },
+            value => value
+        }
+    }
+
+    pub fn remove_equalizer_preset(&mut self, index: usize) {
+        let preset = self.equalizer_presets.remove(index);
+        for profile in &mut self.profiles {
+            for value in profile.settings.values_mut() {
+                if *value == SettingValue::Bands(EqualizerConfig::UserPreset(preset.id)) {
+                    *value = SettingValue::Bands(EqualizerConfig::Custom(preset.levels.clone()));
+                }
+            }
+        }
+    }
 }
 
-pub static START_QUIET: Lazy<bool> = Lazy::new(|| std::env::args().any(|arg| arg.eq("--quiet")));
-pub static CLOSE_IMMEDIATELY: Lazy<bool> = Lazy::new(|| std::env::args().any(|arg| arg.eq("--close-on-quit")));
-pub static DUMMY_DEVICE: Lazy<bool> = Lazy::new(|| std::env::args().any(|arg| arg.eq("--dummy-device")));
-pub static PRINT_UDEV_RULES: Lazy<bool> = Lazy::new(|| std::env::args().any(|arg| arg.eq("--print-udev-rules")));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn equalizer() -> SettingDescriptor {
+        let kind = SettingKind::Bands {
+            count: 2,
+            min: 0,
+            max: 10,
+            presets: vec![String::from("Flat"), String::from("Bass")]
+        };
+        let default = SettingValue::Bands(EqualizerConfig::Preset(String::from("Flat")));
+        SettingDescriptor::new(SettingId::Equalizer, "Equalizer", SettingScope::Profile, kind, default)
+    }
+
+    #[test]
+    fn legacy_profile_is_migrated() {
+        let mut profile: Profile =
+            ron::from_str(r#"(name: "Old", side_tone: 3, volume_limiter: false, microphone_volume: 5, equalizer: Preset(1))"#).unwrap();
+        profile.migrate();
+        assert_eq!(profile.settings.0.get(&SettingId::SideTone), Some(&SettingValue::Level(3)));
+        assert_eq!(profile.settings.0.get(&SettingId::VolumeLimiter), Some(&SettingValue::Toggle(false)));
+        assert_eq!(profile.settings.0.get(&SettingId::MicrophoneVolume), Some(&SettingValue::Level(5)));
+        let preset = SettingValue::Bands(EqualizerConfig::Preset(String::from("Bass")));
+        assert_eq!(profile.settings.value(&equalizer(), &[]), &preset);
+        assert!(!ron::to_string(&profile).unwrap().contains("side_tone"));
+    }
+
+    #[test]
+    fn legacy_headset_is_migrated() {
+        let mut headset: HeadsetConfig = ron::from_str(
+            r#"(os_audio: Disabled, mic_light: 2, bluetooth_call: Mute, auto_enable_bluetooth: true, inactive_time: 45,
+                selected_profile_index: 0, profiles: [])"#
+        )
+        .unwrap();
+        headset.migrate();
+        assert_eq!(headset.settings.0.get(&SettingId::MicrophoneLight), Some(&SettingValue::Level(2)));
+        assert_eq!(headset.settings.0.get(&SettingId::BluetoothCall), Some(&SettingValue::Choice(2)));
+        assert_eq!(headset.settings.0.get(&SettingId::AutoBluetooth), Some(&SettingValue::Toggle(true)));
+        assert_eq!(headset.settings.0.get(&SettingId::InactiveTime), Some(&SettingValue::Level(45)));
+    }
+
+    #[test]
+    fn unknown_presets_are_reset() {
+        let descriptor = equalizer();
+        let mut settings = SettingMap::default();
+        for value in [EqualizerConfig::Preset(String::from("Gone")), EqualizerConfig::Preset(String::from("7")), EqualizerConfig::UserPreset(3)] {
+            settings.0.insert(SettingId::Equalizer, SettingValue::Bands(value));
+            assert_eq!(settings.value(&descriptor, &[]), &descriptor.default);
+        }
+        let user_presets = [EqualizerPreset {
+            id: 3,
+            name: String::from("Mine"),
+            levels: vec![5, 5]
+        }];
+        let user_preset = SettingValue::Bands(EqualizerConfig::UserPreset(3));
+        settings.0.insert(SettingId::Equalizer, user_preset.clone());
+        assert_eq!(settings.value(&descriptor, &user_presets), &user_preset);
+    }
+
+    #[test]
+    fn preset_names_are_unique() {
+        let mut presets = Vec::new();
+        for _ in 0..3 {
+            add_equalizer_preset(&mut presets, "Preset", Vec::new());
+        }
+        presets.remove(1);
+        add_equalizer_preset(&mut presets, "Preset", Vec::new());
+        let names = presets.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["Preset", "Preset 3", "Preset 2"]);
+        let ids = presets.iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(ids, [0, 2, 3]);
+    }
+}
@@ -0,0 +1,245 @@
+use color_eyre::Result;
+use tokio::runtime::Runtime;
+use tokio::sync::watch;
+use zbus::{dbus_interface, fdo, Connection, ConnectionBuilder, SignalContext};
+
+use crate::cli::Status;
+use crate::devices::SettingId;
+use crate::ipc::{dispatch, IpcRequest, IpcResponse};
+use crate::{EventSender, SyncEventSender};
+
+const NAME: &str = "io.github.sidit77.HeadsetController";
+const PATH: &str = "/io/github/sidit77/HeadsetController";
+
+struct Headset {
+    status: Option<Status>,
+    events: SyncEventSender
+}
+
+impl Headset {
+    async fn request(&self, request: IpcRequest) -> fdo::Result<()> {
+        match dispatch(request, self.events.get()).await {
+            IpcResponse::Error { message, .. } => Err(fdo::Error::Failed(message)),
+            _ => Ok(())
+        }
+    }
+
+    /// Only answers once the headset received the new value
+    async fn set_setting(&self, id: SettingId, level: u8) -> fdo::Result<()> {
+        let request = IpcRequest::SetSettingById {
+            id,
+            value: level.to_string()
+        };
+        self.request(request).await?;
+        self.request(IpcRequest::Flush).await
+    }
+}
+
+#[dbus_interface(name = "io.github.sidit77.HeadsetController.Headset")]
+impl Headset {
+    async fn select_profile(&self, profile: String) -> fdo::Result<()> {
+        self.request(IpcRequest::SelectProfile(profile)).await
+    }
+
+    async fn set_side_tone(&self, level: u8) -> fdo::Result<()> {
+        self.set_setting(SettingId::SideTone, level).await
+    }
+
+    async fn set_microphone_volume(&self, level: u8) -> fdo::Result<()> {
+        self.set_setting(SettingId::MicrophoneVolume, level).await
+    }
+
+    async fn show_window(&self) -> fdo::Result<()> {
+        self.request(IpcRequest::ShowWindow).await
+    }
+
+    #[dbus_interface(property)]
+    fn device(&self) -> String {
+        self.status
+            .as_ref()
+            .map(|s| s.device.clone())
+            .unwrap_or_default()
+    }
+
+    #[dbus_interface(property)]
+    fn connected(&self) -> bool {
+        self.status.as_ref().map_or(false, |s| s.connected)
+    }
+
+    /// The battery level in percent or -1 if it is unknown
+    #[dbus_interface(property)]
+    fn battery(&self) -> i32 {
+        self.status
+            .as_ref()
+            .and_then(|s| s.battery)
+            .map_or(-1, i32::from)
+    }
+
+    #[dbus_interface(property)]
+    fn charging(&self) -> bool {
+        self.status.as_ref().map_or(false, |s| s.charging)
+    }
+
+    /// The game and chat volume in percent
+    #[dbus_interface(property)]
+    fn chat_mix(&self) -> (u8, u8) {
+        self.status
+            .as_ref()
+            .and_then(|s| s.chat_mix.as_ref())
+            .map_or((100, 100), |mix| (mix.game, mix.chat))
+    }
+
+    #[dbus_interface(property)]
+    fn profile(&self) -> String {
+        self.status
+            .as_ref()
+            .map(|s| s.profile.clone())
+            .unwrap_or_default()
+    }
+}
+
+pub struct DbusService {
+    // Dropping the sender stops the task, which releases the name
+    updates: watch::Sender<Option<Status>>
+}
+
+impl DbusService {
+    pub fn start(runtime: &Runtime, events: EventSender) -> Result<Self> {
+        Self::serve(runtime, ConnectionBuilder::session()?, events)
+    }
+
+    fn serve(runtime: &Runtime, builder: ConnectionBuilder<'_>, events: EventSender) -> Result<Self> {
+        let headset = Headset {
+            status: None,
+            events: SyncEventSender::new(events)
+        };
+        let connection = runtime.block_on(builder.name(NAME)?.serve_at(PATH, headset)?.build())?;
+        tracing::info!("Registered {} on the session bus", NAME);
+        let (updates, receiver) = watch::channel(None);
+        runtime.spawn(publish(connection, receiver));
+        Ok(Self { updates })
+    }
+
+    pub fn update(&self, status: Option<&Status>) {
+        self.updates.send_replace(status.cloned());
+    }
+}
+
+/// Applies the updates one after another, so an older status can never overwrite a newer one
+async fn publish(connection: Connection, mut updates: watch::Receiver<Option<Status>>) {
+    while updates.changed().await.is_ok() {
+        let status = updates.borrow_and_update().clone();
+        update(&connection, status)
+            .await
+            .unwrap_or_else(|err| tracing::warn!("Could not update the dbus properties: {:?}", err));
+    }
+}
+
+async fn update(connection: &Connection, status: Option<Status>) -> zbus::Result<()> {
+    let iface = connection
+        .object_server()
+        .interface::<_, Headset>(PATH)
+        .await?;
+    let mut headset = iface.get_mut().await;
+    let old = std::mem::replace(&mut headset.status, status);
+    let (old, new) = (old.as_ref(), headset.status.clone());
+    let new = new.as_ref();
+    let ctx: &SignalContext = iface.signal_context();
+    if old.map(|s| &s.device) != new.map(|s| &s.device) {
+        headset.device_changed(ctx).await?;
+    }
+    if old.map(|s| s.connected) != new.map(|s| s.connected) {
+        headset.connected_changed(ctx).await?;
+    }
+    if old.map(|s| s.battery) != new.map(|s| s.battery) {
+        headset.battery_changed(ctx).await?;
+    }
+    if old.map(|s| s.charging) != new.map(|s| s.charging) {
+        headset.charging_changed(ctx).await?;
+    }
+    if old.map(|s| &s.chat_mix) != new.map(|s| &s.chat_mix) {
+        headset.chat_mix_changed(ctx).await?;
+    }
+    if old.map(|s| &s.profile) != new.map(|s| &s.profile) {
+        headset.profile_changed(ctx).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use tokio::runtime::Builder;
+    use zbus::{CacheProperties, ProxyBuilder};
+
+    use super::*;
+
+    const INTERFACE: &str = "io.github.sidit77.HeadsetController.Headset";
+
+    /// Starts a private session bus, `None` if `dbus-daemon` is not installed
+    fn private_bus() -> Option<(Child, String)> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| eprintln!("Skipping the test, could not start dbus-daemon: {}", err))
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+        Some((daemon, address.trim().to_string()))
+    }
+
+    fn status(battery: u8) -> Status {
+        Status {
+            device: String::from("Dummy"),
+            connected: true,
+            battery: Some(battery),
+            charging: false,
+            chat_mix: None,
+            profile: String::from("Default")
+        }
+    }
+
+    #[test]
+    fn properties_follow_the_latest_update() {
+        let Some((mut daemon, address)) = private_bus() else {
+            return;
+        };
+        let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+        let (events, _receiver) = mpsc::channel();
+        let builder = ConnectionBuilder::address(address.as_str()).unwrap();
+        let service = DbusService::serve(&runtime, builder, EventSender::Channel(events)).unwrap();
+        for level in 0..=100 {
+            service.update(Some(&status(level)));
+        }
+        let levels = runtime
+            .block_on(async {
+                let connection = ConnectionBuilder::address(address.as_str())?
+                    .build()
+                    .await?;
+                let proxy = ProxyBuilder::new_bare(&connection)
+                    .destination(NAME)?
+                    .path(PATH)?
+                    .interface(INTERFACE)?
+                    .cache_properties(CacheProperties::No)
+                    .build()
+                    .await?;
+                let mut levels = Vec::new();
+                for _ in 0..50 {
+                    levels.push(proxy.get_property::<i32>("Battery").await?);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                zbus::Result::Ok(levels)
+            })
+            .unwrap();
+        daemon.kill().unwrap();
+        assert_eq!(levels.last(), Some(&100));
+        assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]), "went backwards: {:?}", levels);
+    }
+}
@@ -3,26 +3,22 @@ use std::time::{Duration, Instant};
 use fixed_map::{Key, Map};
 use tracing::instrument;
 
+use crate::devices::SettingId;
 use crate::util::PeekExt;
 
 #[derive(Debug, Clone, Copy, Key, Eq, PartialEq)]
 pub enum Action {
     SaveConfig,
 
-    UpdateSideTone,
-    UpdateEqualizer,
-    UpdateMicrophoneVolume,
-    UpdateVolumeLimit,
-
-    UpdateInactiveTime,
-    UpdateMicrophoneLight,
-    UpdateBluetoothCall,
-    UpdateAutoBluetooth,
+    UpdateSetting(SettingId),
 
     UpdateSystemAudio,
+    UpdateSoftwareEqualizer,
+    UpdateMicrophoneChain,
     UpdateTray,
     UpdateTrayTooltip,
     UpdateDeviceStatus,
+    PublishStatus,
     RefreshDeviceList,
     SwitchDevice
 }
@@ -32,6 +28,7 @@ impl Action {
         match self {
             Action::SaveConfig => Duration::from_secs(10),
             Action::SwitchDevice | Action::RefreshDeviceList => Duration::from_millis(10),
+            Action::PublishStatus => Duration::from_millis(100),
             //Action::UpdateDeviceStatus => Duration::from_millis(250),
             _ => Duration::from_millis(500)
         }
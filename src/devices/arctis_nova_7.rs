@@ -6,6 +6,7 @@ use crossbeam_utils::atomic::AtomicCell;
 use static_assertions::const_assert;
 use tokio::spawn;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
 use tokio::time::timeout;
 use tracing::instrument;
@@ -97,7 +98,7 @@ pub struct ArctisNova7 {
     pub strings: DeviceStrings,
     update_task: JoinHandle<()>,
     config_task: JoinHandle<()>,
-    config_channel: UnboundedSender<ConfigAction>,
+    config_channel: UnboundedSender<ConfigRequest>,
     state: Arc<AtomicCell<State>>
 }
 
@@ -144,12 +145,23 @@ impl ArctisNova7 {
     }
 
     fn request_config_action(&self, action: ConfigAction) {
+        self.send_config_request(ConfigRequest::Apply(action))
+    }
+
+    fn send_config_request(&self, request: ConfigRequest) {
         self.config_channel
-            .send(action)
+            .send(request)
             .unwrap_or_else(|_| tracing::warn!("config channel close unexpectedly"))
     }
 }
 
+#[derive(Debug)]
+enum ConfigRequest {
+    Apply(ConfigAction),
+    /// Answered once every request before it was written to the device
+    Flush(oneshot::Sender<()>)
+}
+
 const STATUS_BUF_SIZE: usize = 8;
 
 #[instrument(skip_all)]
@@ -176,7 +188,7 @@ async fn load_state(config_interface: &HidDevice) -> DeviceResult<State> {
 }
 
 #[instrument(skip_all)]
-async fn configuration_handler(config_interface: HidDevice, events: UpdateChannel, mut config_requests: UnboundedReceiver<ConfigAction>) {
+async fn configuration_handler(config_interface: HidDevice, events: UpdateChannel, mut config_requests: UnboundedReceiver<ConfigRequest>) {
     let mut config_interface = MaybeHidDevice::from(config_interface);
 
     loop {
@@ -185,7 +197,8 @@ async fn configuration_handler(config_interface: HidDevice, events: UpdateChanne
             false => Duration::MAX
         };
         match timeout(duration, config_requests.recv()).await {
-            Ok(Some(request)) => {
+            Ok(Some(ConfigRequest::Flush(done))) => _ = done.send(()),
+            Ok(Some(ConfigRequest::Apply(request))) => {
                 tracing::debug!("Attempting apply config request: {:?}", request);
                 let data = match request {
                     ConfigAction::SetSideTone(level) => vec![0x00, 0x39, level],
@@ -305,6 +318,15 @@ impl Device for ArctisNova7 {
         Some(self.state.load().battery())
     }
 
+    fn flush(&self) -> FlushFuture {
+        let (sender, receiver) = oneshot::channel();
+        self.send_config_request(ConfigRequest::Flush(sender));
+        Box::pin(async move {
+            // A closed channel means that there is nothing left to wait for
+            let _ = receiver.await;
+        })
+    }
+
     fn get_chat_mix(&self) -> Option<ChatMix> {
         Some(self.state.load().chat_mix)
     }
@@ -367,10 +389,6 @@ impl VolumeLimiter for ArctisNova7 {
 }
 
 impl Equalizer for ArctisNova7 {
-    fn bands(&self) -> u8 {
-        10
-    }
-
     fn base_level(&self) -> u8 {
         0x14
     }
@@ -379,6 +397,25 @@ impl Equalizer for ArctisNova7 {
         0x14
     }
 
+    fn band_layout(&self) -> &[EqualizerBand] {
+        &[
+            EqualizerBand::new(32.0, 1.414),
+            EqualizerBand::new(64.0, 1.414),
+            EqualizerBand::new(125.0, 1.414),
+            EqualizerBand::new(250.0, 1.414),
+            EqualizerBand::new(500.0, 1.414),
+            EqualizerBand::new(1000.0, 1.414),
+            EqualizerBand::new(2000.0, 1.414),
+            EqualizerBand::new(4000.0, 1.414),
+            EqualizerBand::new(8000.0, 1.414),
+            EqualizerBand::new(16000.0, 1.414)
+        ]
+    }
+
+    fn step_size(&self) -> f64 {
+        0.5
+    }
+
     fn presets(&self) -> &[(&str, &[u8])] {
         &[
             ("Flat", &[0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14]),
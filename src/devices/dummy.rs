@@ -2,7 +2,7 @@ use std::future::ready;
 
 use tracing::instrument;
 
-use crate::config::CallAction;
+use crate::config::{CallAction, NoiseCancellingMode};
 use crate::devices::*;
 
 pub const DUMMY_DEVICE: SupportedDevice = SupportedDevice {
@@ -63,6 +63,10 @@ impl Device for DummyDevice {
     fn get_mic_light(&self) -> Option<&dyn MicrophoneLight> {
         Some(self)
     }
+
+    fn get_noise_cancelling(&self) -> Option<&dyn NoiseCancelling> {
+        Some(self)
+    }
 }
 
 impl SideTone for DummyDevice {
@@ -99,10 +103,6 @@ impl MicrophoneLight for DummyDevice {
 }
 
 impl Equalizer for DummyDevice {
-    fn bands(&self) -> u8 {
-        13
-    }
-
     fn base_level(&self) -> u8 {
         8
     }
@@ -111,6 +111,28 @@ impl Equalizer for DummyDevice {
         3
     }
 
+    fn band_layout(&self) -> &[EqualizerBand] {
+        &[
+            EqualizerBand::new(32.0, 2.15),
+            EqualizerBand::new(50.0, 2.15),
+            EqualizerBand::new(80.0, 2.15),
+            EqualizerBand::new(125.0, 2.15),
+            EqualizerBand::new(200.0, 2.15),
+            EqualizerBand::new(315.0, 2.15),
+            EqualizerBand::new(500.0, 2.15),
+            EqualizerBand::new(800.0, 2.15),
+            EqualizerBand::new(1250.0, 2.15),
+            EqualizerBand::new(2000.0, 2.15),
+            EqualizerBand::new(3150.0, 2.15),
+            EqualizerBand::new(5000.0, 2.15),
+            EqualizerBand::new(8000.0, 2.15)
+        ]
+    }
+
+    fn step_size(&self) -> f64 {
+        2.0
+    }
+
     fn presets(&self) -> &[(&str, &[u8])] {
         &[("Default", &[8; 13])]
     }
@@ -146,3 +168,19 @@ impl InactiveTime for DummyDevice {
         tracing::info!("Updated inactive time");
     }
 }
+
+impl NoiseCancelling for DummyDevice {
+    fn transparency_levels(&self) -> u8 {
+        10
+    }
+
+    #[instrument(skip(self))]
+    fn set_mode(&self, mode: NoiseCancellingMode) {
+        tracing::info!("Updated noise cancelling mode");
+    }
+
+    #[instrument(skip(self))]
+    fn set_transparency_level(&self, level: u8) {
+        tracing::info!("Updated transparency level");
+    }
+}
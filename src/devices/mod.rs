@@ -8,13 +8,15 @@ use std::pin::Pin;
 
 use async_hid::{DeviceInfo, HidError};
 use color_eyre::eyre::Error as EyreError;
+use fixed_map::Key;
 use futures_lite::stream::StreamExt;
-use tao::event_loop::EventLoopProxy;
+use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use crate::config::{CallAction, DUMMY_DEVICE as DUMMY_DEVICE_ENABLED};
+use crate::config::{CallAction, EqualizerConfig, EqualizerPreset, NoiseCancellingMode};
 use crate::devices::arctis_nova_7::{ARCTIS_NOVA_7, ARCTIS_NOVA_7P, ARCTIS_NOVA_7X};
 use crate::devices::dummy::DUMMY_DEVICE;
+use crate::EventSender;
 
 pub const SUPPORTED_DEVICES: &[SupportedDevice] = &[ARCTIS_NOVA_7, ARCTIS_NOVA_7X, ARCTIS_NOVA_7P];
 
@@ -88,7 +90,7 @@ impl DeviceStrings {
 }
 
 pub type InterfaceMap = HashMap<Interface, DeviceInfo>;
-pub type UpdateChannel = EventLoopProxy<DeviceUpdate>;
+pub type UpdateChannel = EventSender;
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct SupportedDevice {
     pub strings: DeviceStrings,
@@ -119,12 +121,16 @@ pub enum DeviceUpdate {
 #[derive(Debug, Clone, Default)]
 pub struct DeviceManager {
     interfaces: InterfaceMap,
-    devices: Vec<SupportedDevice>
+    devices: Vec<SupportedDevice>,
+    include_dummy: bool
 }
 
 impl DeviceManager {
-    pub async fn new() -> DeviceResult<Self> {
-        let mut result = Self::default();
+    pub async fn new(include_dummy: bool) -> DeviceResult<Self> {
+        let mut result = Self {
+            include_dummy,
+            ..Self::default()
+        };
         result.refresh().await?;
         Ok(result)
     }
@@ -142,7 +148,7 @@ impl DeviceManager {
         self.devices.extend(
             SUPPORTED_DEVICES
                 .iter()
-                .chain(DUMMY_DEVICE_ENABLED.then_some(&DUMMY_DEVICE))
+                .chain(self.include_dummy.then_some(&DUMMY_DEVICE))
                 .filter(|dev| {
                     dev.required_interfaces
                         .iter()
@@ -208,6 +214,86 @@ pub fn generate_udev_rules() -> DeviceResult<String> {
     Ok(rules)
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Key, Serialize, Deserialize)]
+pub enum SettingId {
+    Equalizer,
+    SideTone,
+    MicrophoneVolume,
+    VolumeLimiter,
+    InactiveTime,
+    MicrophoneLight,
+    AutoBluetooth,
+    BluetoothCall,
+    NoiseCancelling,
+    TransparencyLevel
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SettingScope {
+    Profile,
+    Headset
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SettingKind {
+    Slider { min: u8, max: u8 },
+    Toggle,
+    Enum { options: &'static [&'static str] },
+    /// `presets` are the names of the presets built into the device
+    Bands { count: u8, min: u8, max: u8, presets: Vec<String> }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SettingValue {
+    Level(u8),
+    Toggle(bool),
+    Choice(u8),
+    Bands(EqualizerConfig)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SettingDescriptor {
+    pub id: SettingId,
+    pub label: &'static str,
+    pub tooltip: Option<&'static str>,
+    pub scope: SettingScope,
+    pub kind: SettingKind,
+    pub default: SettingValue
+}
+
+impl SettingDescriptor {
+    pub const fn new(id: SettingId, label: &'static str, scope: SettingScope, kind: SettingKind, default: SettingValue) -> Self {
+        Self {
+            id,
+            label,
+            tooltip: None,
+            scope,
+            kind,
+            default
+        }
+    }
+
+    pub fn with_tooltip(mut self, tooltip: &'static str) -> Self {
+        self.tooltip = Some(tooltip);
+        self
+    }
+
+    /// Whether the value is valid for this setting, `user_presets` are the equalizer presets saved by the user
+    pub fn accepts(&self, value: &SettingValue, user_presets: &[EqualizerPreset]) -> bool {
+        match (&self.kind, value) {
+            (SettingKind::Slider { min, max }, SettingValue::Level(level)) => (*min..=*max).contains(level),
+            (SettingKind::Toggle, SettingValue::Toggle(_)) => true,
+            (SettingKind::Enum { options }, SettingValue::Choice(choice)) => (*choice as usize) < options.len(),
+            (SettingKind::Bands { count, min, max, .. }, SettingValue::Bands(EqualizerConfig::Custom(levels))) => {
+                levels.len() == *count as usize && levels.iter().all(|l| (*min..=*max).contains(l))
+            }
+            (SettingKind::Bands { presets, .. }, SettingValue::Bands(EqualizerConfig::Preset(name))) => presets.contains(name),
+            (SettingKind::Bands { .. }, SettingValue::Bands(EqualizerConfig::UserPreset(id))) => user_presets.iter().any(|p| p.id == *id),
+            _ => false
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 #[non_exhaustive]
 enum ConfigAction {
@@ -224,6 +310,7 @@ enum ConfigAction {
 pub type DeviceResult<T> = Result<T, EyreError>;
 pub type BoxedDevice = Box<dyn Device>;
 pub type BoxedDeviceFuture<'a> = Pin<Box<dyn Future<Output = DeviceResult<BoxedDevice>> + 'a>>;
+pub type FlushFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
 
 pub trait Device {
     fn strings(&self) -> DeviceStrings;
@@ -235,6 +322,10 @@ pub trait Device {
     fn get_battery_status(&self) -> Option<BatteryLevel> {
         None
     }
+    /// Resolves once every setting applied so far was sent to the device
+    fn flush(&self) -> FlushFuture {
+        Box::pin(std::future::ready(()))
+    }
     fn get_chat_mix(&self) -> Option<ChatMix> {
         None
     }
@@ -259,6 +350,171 @@ pub trait Device {
     fn get_mic_light(&self) -> Option<&dyn MicrophoneLight> {
         None
     }
+    fn get_noise_cancelling(&self) -> Option<&dyn NoiseCancelling> {
+        None
+    }
+
+    fn settings(&self) -> Vec<SettingDescriptor> {
+        let mut settings = Vec::new();
+        if let Some(equalizer) = self.get_equalizer() {
+            let kind = SettingKind::Bands {
+                count: equalizer.bands(),
+                min: equalizer.base_level() - equalizer.variance(),
+                max: equalizer.base_level() + equalizer.variance(),
+                presets: equalizer
+                    .presets()
+                    .iter()
+                    .map(|(name, _)| name.to_string())
+                    .collect()
+            };
+            let default = match equalizer.presets().first() {
+                Some((name, _)) => SettingValue::Bands(EqualizerConfig::Preset(name.to_string())),
+                None => SettingValue::Bands(EqualizerConfig::Custom(vec![equalizer.base_level(); equalizer.bands() as usize]))
+            };
+            settings.push(SettingDescriptor::new(SettingId::Equalizer, "Equalizer", SettingScope::Profile, kind, default));
+        }
+        if let Some(side_tone) = self.get_side_tone() {
+            let kind = SettingKind::Slider {
+                min: 0,
+                max: side_tone.levels().saturating_sub(1)
+            };
+            let default = SettingValue::Level(0);
+            settings.push(
+                SettingDescriptor::new(SettingId::SideTone, "Side Tone Level", SettingScope::Profile, kind, default).with_tooltip(
+                    "This setting controls how much of your voice is played back over the headset when you speak.\nSet to 0 to turn off."
+                )
+            );
+        }
+        if let Some(mic_volume) = self.get_mic_volume() {
+            let kind = SettingKind::Slider {
+                min: 0,
+                max: mic_volume.levels().saturating_sub(1)
+            };
+            let default = SettingValue::Level(0);
+            settings.push(SettingDescriptor::new(SettingId::MicrophoneVolume, "Microphone Level", SettingScope::Profile, kind, default));
+        }
+        if let Some(noise_cancelling) = self.get_noise_cancelling() {
+            let kind = SettingKind::Enum {
+                options: NoiseCancellingMode::NAMES
+            };
+            let default = SettingValue::Choice(0);
+            settings.push(SettingDescriptor::new(SettingId::NoiseCancelling, "Noise Cancelling", SettingScope::Profile, kind, default));
+            let kind = SettingKind::Slider {
+                min: 0,
+                max: noise_cancelling.transparency_levels().saturating_sub(1)
+            };
+            let default = SettingValue::Level(0);
+            settings.push(
+                SettingDescriptor::new(SettingId::TransparencyLevel, "Transparency Level", SettingScope::Profile, kind, default)
+                    .with_tooltip("This setting controls how much of the environment is passed through in transparency mode.")
+            );
+        }
+        if self.get_volume_limiter().is_some() {
+            let default = SettingValue::Toggle(true);
+            settings.push(SettingDescriptor::new(SettingId::VolumeLimiter, "Limit Volume", SettingScope::Profile, SettingKind::Toggle, default));
+        }
+        if self.get_inactive_time().is_some() {
+            let kind = SettingKind::Slider { min: 5, max: 120 };
+            let default = SettingValue::Level(30);
+            settings.push(SettingDescriptor::new(SettingId::InactiveTime, "Inactive Time", SettingScope::Headset, kind, default));
+        }
+        if let Some(mic_light) = self.get_mic_light() {
+            let kind = SettingKind::Slider {
+                min: 0,
+                max: mic_light.levels().saturating_sub(1)
+            };
+            let default = SettingValue::Level(0);
+            settings.push(SettingDescriptor::new(SettingId::MicrophoneLight, "Microphone Light", SettingScope::Headset, kind, default));
+        }
+        if self.get_bluetooth_config().is_some() {
+            let default = SettingValue::Toggle(false);
+            settings.push(SettingDescriptor::new(
+                SettingId::AutoBluetooth,
+                "Auto Enable Bluetooth",
+                SettingScope::Headset,
+                SettingKind::Toggle,
+                default
+            ));
+            let kind = SettingKind::Enum { options: CallAction::NAMES };
+            let default = SettingValue::Choice(0);
+            settings.push(SettingDescriptor::new(SettingId::BluetoothCall, "Bluetooth Call Action", SettingScope::Headset, kind, default));
+        }
+        settings
+    }
+
+    fn apply_setting(&self, id: SettingId, value: &SettingValue) {
+        match (id, value) {
+            (SettingId::Equalizer, SettingValue::Bands(config)) => {
+                if let Some(equalizer) = self.get_equalizer() {
+                    let levels = match config {
+                        EqualizerConfig::Preset(name) => equalizer
+                            .presets()
+                            .iter()
+                            .find(|(n, _)| *n == name.as_str())
+                            .map(|(_, levels)| levels.to_vec()),
+                        EqualizerConfig::UserPreset(_) => None,
+                        EqualizerConfig::Custom(levels) => Some(levels.clone())
+                    };
+                    match levels {
+                        Some(levels) => equalizer.set_levels(&levels),
+                        None => tracing::warn!("Can not resolve equalizer config {:?}", config)
+                    }
+                }
+            }
+            (SettingId::SideTone, SettingValue::Level(level)) => {
+                if let Some(side_tone) = self.get_side_tone() {
+                    side_tone.set_level(*level);
+                }
+            }
+            (SettingId::MicrophoneVolume, SettingValue::Level(level)) => {
+                if let Some(mic_volume) = self.get_mic_volume() {
+                    mic_volume.set_level(*level);
+                }
+            }
+            (SettingId::VolumeLimiter, SettingValue::Toggle(enabled)) => {
+                if let Some(volume_limiter) = self.get_volume_limiter() {
+                    volume_limiter.set_enabled(*enabled);
+                }
+            }
+            (SettingId::InactiveTime, SettingValue::Level(minutes)) => {
+                if let Some(inactive_time) = self.get_inactive_time() {
+                    inactive_time.set_inactive_time(*minutes);
+                }
+            }
+            (SettingId::MicrophoneLight, SettingValue::Level(level)) => {
+                if let Some(mic_light) = self.get_mic_light() {
+                    mic_light.set_light_strength(*level);
+                }
+            }
+            (SettingId::AutoBluetooth, SettingValue::Toggle(enabled)) => {
+                if let Some(bluetooth_config) = self.get_bluetooth_config() {
+                    bluetooth_config.set_auto_enabled(*enabled);
+                }
+            }
+            (SettingId::BluetoothCall, SettingValue::Choice(choice)) => {
+                if let Some(bluetooth_config) = self.get_bluetooth_config() {
+                    match CallAction::ACTIONS.get(*choice as usize) {
+                        Some(action) => bluetooth_config.set_call_action(*action),
+                        None => tracing::warn!(choice, "Unknown call action")
+                    }
+                }
+            }
+            (SettingId::NoiseCancelling, SettingValue::Choice(choice)) => {
+                if let Some(noise_cancelling) = self.get_noise_cancelling() {
+                    match NoiseCancellingMode::MODES.get(*choice as usize) {
+                        Some(mode) => noise_cancelling.set_mode(*mode),
+                        None => tracing::warn!(choice, "Unknown noise cancelling mode")
+                    }
+                }
+            }
+            (SettingId::TransparencyLevel, SettingValue::Level(level)) => {
+                if let Some(noise_cancelling) = self.get_noise_cancelling() {
+                    noise_cancelling.set_transparency_level(*level);
+                }
+            }
+            _ => tracing::warn!("{:?} is not a valid value for {:?}", value, id)
+        }
+    }
 }
 
 pub trait SideTone {
@@ -275,12 +531,34 @@ pub trait MicrophoneVolume {
     fn set_level(&self, level: u8);
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EqualizerBand {
+    pub frequency: f64,
+    pub q: f64
+}
+
+impl EqualizerBand {
+    pub const fn new(frequency: f64, q: f64) -> Self {
+        Self { frequency, q }
+    }
+}
+
 pub trait Equalizer {
-    fn bands(&self) -> u8;
     fn base_level(&self) -> u8;
     fn variance(&self) -> u8;
+    /// Every band of the equalizer, from the lowest frequency to the highest
+    fn band_layout(&self) -> &[EqualizerBand];
+    fn step_size(&self) -> f64;
     fn presets(&self) -> &[(&str, &[u8])];
     fn set_levels(&self, levels: &[u8]);
+
+    fn bands(&self) -> u8 {
+        self.band_layout().len() as u8
+    }
+
+    fn gain(&self, level: u8) -> f64 {
+        (level as f64 - self.base_level() as f64) * self.step_size()
+    }
 }
 
 pub trait BluetoothConfig {
@@ -297,6 +575,12 @@ pub trait InactiveTime {
     fn set_inactive_time(&self, minutes: u8);
 }
 
+pub trait NoiseCancelling {
+    fn transparency_levels(&self) -> u8;
+    fn set_mode(&self, mode: NoiseCancellingMode);
+    fn set_transparency_level(&self, level: u8);
+}
+
 /*
 #[derive(Debug)]
 pub enum DeviceError {
@@ -0,0 +1,199 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+pub const SAMPLE_RATE: f64 = 48000.0;
+pub const MIN_FREQUENCY: f64 = 20.0;
+pub const MAX_FREQUENCY: f64 = 20000.0;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FilterKind {
+    Peaking,
+    LowShelf,
+    HighShelf
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParametricFilter {
+    pub kind: FilterKind,
+    pub frequency: f64,
+    pub gain: f64,
+    pub q: f64
+}
+
+impl ParametricFilter {
+    pub fn biquad(&self) -> Biquad {
+        match self.kind {
+            FilterKind::Peaking => Biquad::peaking(self.frequency, self.q, self.gain),
+            FilterKind::LowShelf => Biquad::low_shelf(self.frequency, self.q, self.gain),
+            FilterKind::HighShelf => Biquad::high_shelf(self.frequency, self.q, self.gain)
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64
+}
+
+impl Biquad {
+    pub fn peaking(frequency: f64, q: f64, gain: f64) -> Self {
+        let a = 10f64.powf(gain / 40.0);
+        let w0 = 2.0 * PI * frequency / SAMPLE_RATE;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        Self::normalized(1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a)
+    }
+
+    pub fn low_shelf(frequency: f64, q: f64, gain: f64) -> Self {
+        let a = 10f64.powf(gain / 40.0);
+        let w0 = 2.0 * PI * frequency / SAMPLE_RATE;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let sqrt = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            a * ((a + 1.0) - (a - 1.0) * cos + sqrt),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - sqrt),
+            (a + 1.0) + (a - 1.0) * cos + sqrt,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - sqrt
+        )
+    }
+
+    pub fn high_shelf(frequency: f64, q: f64, gain: f64) -> Self {
+        let a = 10f64.powf(gain / 40.0);
+        let w0 = 2.0 * PI * frequency / SAMPLE_RATE;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let sqrt = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            a * ((a + 1.0) + (a - 1.0) * cos + sqrt),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - sqrt),
+            (a + 1.0) - (a - 1.0) * cos + sqrt,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - sqrt
+        )
+    }
+
+    fn normalized(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0
+        }
+    }
+
+    pub fn response(&self, frequency: f64) -> f64 {
+        let w = 2.0 * PI * frequency / SAMPLE_RATE;
+        let (sin1, cos1) = w.sin_cos();
+        let (sin2, cos2) = (2.0 * w).sin_cos();
+        let num_re = self.b0 + self.b1 * cos1 + self.b2 * cos2;
+        let num_im = -(self.b1 * sin1 + self.b2 * sin2);
+        let den_re = 1.0 + self.a1 * cos1 + self.a2 * cos2;
+        let den_im = -(self.a1 * sin1 + self.a2 * sin2);
+        let magnitude = (num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im);
+        10.0 * magnitude.log10()
+    }
+}
+
+pub fn combined_response(filters: &[Biquad], frequency: f64) -> f64 {
+    filters
+        .iter()
+        .map(|filter| filter.response(frequency))
+        .sum()
+}
+
+pub fn log_frequencies(count: usize) -> impl Iterator<Item = f64> {
+    let (min, max) = (MIN_FREQUENCY.log10(), MAX_FREQUENCY.log10());
+    (0..count).map(move |i| 10f64.powf(min + (max - min) * i as f64 / (count - 1) as f64))
+}
+
+pub fn format_frequency(frequency: f64) -> String {
+    match frequency >= 1000.0 {
+        true if (frequency / 1000.0).fract() < 0.05 => format!("{:.0}k", frequency / 1000.0),
+        true => format!("{:.1}k", frequency / 1000.0),
+        false => format!("{:.0}", frequency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "expected {expected} dB, got {actual} dB");
+    }
+
+    #[test]
+    fn peaking_reaches_its_gain_at_the_center() {
+        for gain in [-12.0, -3.0, 6.0, 12.0] {
+            let filter = Biquad::peaking(1000.0, 1.414, gain);
+            assert_close(filter.response(1000.0), gain, 0.01);
+            assert_close(filter.response(20.0), 0.0, 0.1);
+            assert_close(filter.response(20000.0), 0.0, 0.1);
+        }
+    }
+
+    #[test]
+    fn zero_gain_is_flat() {
+        let filters = [Biquad::peaking(500.0, 0.7, 0.0), Biquad::low_shelf(100.0, 0.7, 0.0), Biquad::high_shelf(8000.0, 0.7, 0.0)];
+        for frequency in log_frequencies(50) {
+            assert_close(combined_response(&filters, frequency), 0.0, 1e-9);
+        }
+    }
+
+    #[test]
+    fn shelves_boost_their_side() {
+        let low = Biquad::low_shelf(200.0, 0.707, 6.0);
+        assert_close(low.response(20.0), 6.0, 0.1);
+        assert_close(low.response(200.0), 3.0, 0.1);
+        assert_close(low.response(10000.0), 0.0, 0.1);
+        let high = Biquad::high_shelf(4000.0, 0.707, -6.0);
+        assert_close(high.response(20.0), 0.0, 0.1);
+        assert_close(high.response(4000.0), -3.0, 0.1);
+        assert_close(high.response(20000.0), -6.0, 0.3);
+    }
+
+    #[test]
+    fn combined_response_adds_the_filters() {
+        let a = Biquad::peaking(1000.0, 1.0, 4.0);
+        let b = Biquad::peaking(1000.0, 1.0, -1.5);
+        assert_close(combined_response(&[a, b], 1000.0), 2.5, 0.01);
+        assert_close(combined_response(&[], 1000.0), 0.0, 0.0);
+    }
+
+    #[test]
+    fn filter_kinds_map_to_biquads() {
+        let filter = ParametricFilter {
+            kind: FilterKind::HighShelf,
+            frequency: 4000.0,
+            gain: 3.0,
+            q: 0.707
+        };
+        assert_eq!(filter.biquad(), Biquad::high_shelf(4000.0, 0.707, 3.0));
+    }
+
+    #[test]
+    fn log_frequencies_span_the_audible_range() {
+        let frequencies = log_frequencies(3).collect::<Vec<_>>();
+        assert_close(frequencies[0], MIN_FREQUENCY, 1e-9);
+        assert_close(frequencies[1], (MIN_FREQUENCY * MAX_FREQUENCY).sqrt(), 1e-6);
+        assert_close(frequencies[2], MAX_FREQUENCY, 1e-6);
+    }
+
+    #[test]
+    fn frequencies_are_formatted_compactly() {
+        assert_eq!(format_frequency(125.0), "125");
+        assert_eq!(format_frequency(1000.0), "1k");
+        assert_eq!(format_frequency(3150.0), "3.1k");
+        assert_eq!(format_frequency(16000.0), "16k");
+    }
+}
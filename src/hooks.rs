@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use tokio::runtime::Runtime;
+
+use crate::alerts;
+use crate::cli::{Status, StatusChange};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum HookEvent {
+    Connect,
+    Disconnect,
+    /// Runs once when the level drops below the given percentage, and again only after it recovered
+    BatteryBelow(u8),
+    ChargingStarted,
+    /// Charging stopped with a full battery
+    ChargingFinished,
+    /// Charging stopped before the battery was full
+    ChargingStopped,
+    ProfileChange,
+    ChatMixChange
+}
+
+impl HookEvent {
+    /// The value of `HEADSET_EVENT`
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::Connect => "connect",
+            HookEvent::Disconnect => "disconnect",
+            HookEvent::BatteryBelow(_) => "battery_below",
+            HookEvent::ChargingStarted => "charging_started",
+            HookEvent::ChargingFinished => "charging_finished",
+            HookEvent::ChargingStopped => "charging_stopped",
+            HookEvent::ProfileChange => "profile_change",
+            HookEvent::ChatMixChange => "chat_mix_change"
+        }
+    }
+
+    /// `crossed` are the [HookEvent::BatteryBelow] thresholds that fire for a battery change
+    pub fn matches(self, change: &StatusChange, crossed: &[u8], status: &Status) -> bool {
+        match (self, change) {
+            (HookEvent::ChargingFinished, StatusChange::ChargingFinished) => alerts::fully_charged(status),
+            (HookEvent::ChargingStopped, StatusChange::ChargingFinished) => !alerts::fully_charged(status),
+            (HookEvent::Connect, StatusChange::Connected)
+            | (HookEvent::Disconnect, StatusChange::Disconnected)
+            | (HookEvent::ChargingStarted, StatusChange::ChargingStarted)
+            | (HookEvent::ProfileChange, StatusChange::ProfileChanged { .. })
+            | (HookEvent::ChatMixChange, StatusChange::ChatMixChanged) => true,
+            (HookEvent::BatteryBelow(threshold), StatusChange::BatteryChanged { .. }) => crossed.contains(&threshold),
+            _ => false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Hook {
+    pub event: HookEvent,
+    /// Run by `sh -c` or `cmd /C`
+    pub command: String
+}
+
+/// Remembers the battery thresholds that fired, so a level flapping around one does not run its hooks repeatedly
+#[derive(Debug, Default)]
+pub struct Hooks {
+    fired: Vec<u8>
+}
+
+impl Hooks {
+    /// Starts the command of every hook that matches one of the changes without waiting for it
+    pub fn run(&mut self, runtime: &Runtime, hooks: &[Hook], changes: &[StatusChange], status: &Status) {
+        for change in changes {
+            let crossed = self.update(hooks, change);
+            run_matching(runtime, hooks, change, &crossed, status);
+        }
+    }
+
+    /// Returns the thresholds that fire for the change, using the same rearm rule as the battery notifications
+    fn update(&mut self, hooks: &[Hook], change: &StatusChange) -> Vec<u8> {
+        match change {
+            StatusChange::Connected => {
+                self.fired.clear();
+                Vec::new()
+            }
+            StatusChange::BatteryChanged { current, .. } => {
+                self.fired
+                    .retain(|threshold| !alerts::recovered(*threshold, *current));
+                let mut crossed = hooks
+                    .iter()
+                    .filter_map(|hook| match hook.event {
+                        HookEvent::BatteryBelow(threshold) if *current < threshold && !self.fired.contains(&threshold) => Some(threshold),
+                        _ => None
+                    })
+                    .collect::<Vec<_>>();
+                crossed.sort_unstable();
+                crossed.dedup();
+                self.fired.extend_from_slice(&crossed);
+                crossed
+            }
+            _ => Vec::new()
+        }
+    }
+}
+
+fn run_matching(runtime: &Runtime, hooks: &[Hook], change: &StatusChange, crossed: &[u8], status: &Status) {
+    for hook in hooks.iter().filter(|hook| hook.event.matches(change, crossed, status)) {
+        let _span = tracing::info_span!("hook", event = hook.event.name()).entered();
+        let mut env = status_env(status);
+        env.push((String::from("HEADSET_EVENT"), hook.event.name().to_string()));
+        match change {
+            StatusChange::BatteryChanged { previous, .. } => {
+                env.push((String::from("HEADSET_PREVIOUS_BATTERY"), previous.map_or_else(String::new, |level| level.to_string())));
+            }
+            StatusChange::ProfileChanged { previous } => {
+                env.push((String::from("HEADSET_PREVIOUS_PROFILE"), previous.clone()));
+            }
+            _ => {}
+        }
+        spawn_command(runtime, &hook.command, env);
+    }
+}
+
+/// `HEADSET_<PLACEHOLDER>` for every placeholder of the status
+pub fn status_env(status: &Status) -> Vec<(String, String)> {
+    status
+        .placeholders()
+        .into_iter()
+        .map(|(name, value)| (format!("HEADSET_{}", name.to_uppercase()), value))
+        .collect()
+}
+
+/// Starts the command line in a shell without waiting for it
+pub fn spawn_command(runtime: &Runtime, line: &str, env: Vec<(String, String)>) {
+    let mut command = shell(line);
+    command.envs(env);
+    let _guard = runtime.enter();
+    match command.spawn() {
+        Ok(mut child) => {
+            let line = line.to_string();
+            runtime.spawn(async move {
+                match child.wait().await {
+                    Ok(status) if !status.success() => tracing::warn!("Hook \"{}\" failed with {}", line, status),
+                    Ok(_) => tracing::debug!("Hook \"{}\" finished", line),
+                    Err(err) => tracing::warn!("Could not wait for hook \"{}\": {}", line, err)
+                }
+            });
+        }
+        Err(err) => tracing::warn!("Could not start hook \"{}\": {}", line, err)
+    }
+}
+
+#[cfg(unix)]
+fn shell(line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(line);
+    command
+}
+
+#[cfg(windows)]
+fn shell(line: &str) -> Command {
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(line).creation_flags(CREATE_NO_WINDOW);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn battery(current: u8) -> StatusChange {
+        StatusChange::BatteryChanged { previous: None, current }
+    }
+
+    #[test]
+    fn battery_below_fires_once_until_recovered() {
+        let hooks = [Hook {
+            event: HookEvent::BatteryBelow(15),
+            command: String::new()
+        }];
+        let mut state = Hooks::default();
+        assert_eq!(state.update(&hooks, &battery(14)), vec![15]);
+        for level in [15, 14, 16, 13, 19, 14] {
+            assert!(state.update(&hooks, &battery(level)).is_empty(), "fired again at {}%", level);
+        }
+        assert!(state.update(&hooks, &battery(20)).is_empty());
+        assert_eq!(state.update(&hooks, &battery(14)), vec![15]);
+        state.update(&hooks, &StatusChange::Connected);
+        assert_eq!(state.update(&hooks, &battery(14)), vec![15]);
+    }
+
+    #[test]
+    fn unplugging_early_only_stops_charging() {
+        let mut status = Status {
+            device: String::from("Headset"),
+            connected: true,
+            battery: Some(60),
+            charging: false,
+            chat_mix: None,
+            profile: String::from("Default")
+        };
+        let change = StatusChange::ChargingFinished;
+        assert!(!HookEvent::ChargingFinished.matches(&change, &[], &status));
+        assert!(HookEvent::ChargingStopped.matches(&change, &[], &status));
+        status.battery = Some(100);
+        assert!(HookEvent::ChargingFinished.matches(&change, &[], &status));
+        assert!(!HookEvent::ChargingStopped.matches(&change, &[], &status));
+    }
+}
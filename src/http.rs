@@ -0,0 +1,224 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router, Server};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::cli::{SettingEntry, Status, EXIT_INVALID_ARGUMENT, EXIT_NO_DEVICE};
+use crate::config::HttpConfig;
+use crate::devices::DeviceUpdate;
+use crate::ipc::{dispatch, IpcRequest, IpcResponse};
+use crate::{EventSender, SyncEventSender};
+
+/// Pushed to every connected WebSocket
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum ApiEvent {
+    Device { update: DeviceEvent, status: Option<Status> },
+    Request { request: IpcRequest, response: IpcResponse }
+}
+
+/// The serializable part of a [DeviceUpdate]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum DeviceEvent {
+    ConnectionChanged,
+    ChatMixChanged,
+    BatteryLevel,
+    DeviceError { message: String }
+}
+
+impl From<&DeviceUpdate> for DeviceEvent {
+    fn from(update: &DeviceUpdate) -> Self {
+        match update {
+            DeviceUpdate::ConnectionChanged => Self::ConnectionChanged,
+            DeviceUpdate::ChatMixChanged => Self::ChatMixChanged,
+            DeviceUpdate::BatteryLevel => Self::BatteryLevel,
+            DeviceUpdate::DeviceError(err) => Self::DeviceError { message: err.to_string() }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    events: Arc<SyncEventSender>,
+    token: Arc<str>,
+    broadcast: broadcast::Sender<String>
+}
+
+impl ApiState {
+    async fn dispatch(&self, request: IpcRequest) -> IpcResponse {
+        dispatch(request, self.events.get()).await
+    }
+}
+
+pub struct HttpServer {
+    broadcast: broadcast::Sender<String>,
+    task: JoinHandle<()>
+}
+
+impl HttpServer {
+    pub fn start(runtime: &Runtime, config: &HttpConfig, events: EventSender) -> Result<Self> {
+        let (broadcast, _) = broadcast::channel(32);
+        let state = ApiState {
+            events: Arc::new(SyncEventSender::new(events)),
+            token: Arc::from(config.token.as_str()),
+            broadcast: broadcast.clone()
+        };
+        let router = Router::new()
+            .route("/api/status", get(status))
+            .route("/api/profiles", get(profiles))
+            .route("/api/profiles/select", post(select_profile))
+            .route("/api/settings", get(settings))
+            .route("/api/settings/:setting", put(set_setting))
+            .route("/api/events", get(events_socket))
+            .layer(middleware::from_fn_with_state(state.clone(), authorize))
+            .with_state(state);
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, config.port));
+        let _guard = runtime.enter();
+        let server = Server::try_bind(&address)?.serve(router.into_make_service());
+        let task = runtime.spawn(async move {
+            server
+                .await
+                .unwrap_or_else(|err| tracing::error!("The http server stopped: {}", err))
+        });
+        tracing::info!("Serving the http api on http://{}", address);
+        Ok(Self { broadcast, task })
+    }
+
+    pub fn publish(&self, event: &ApiEvent) {
+        // Sending only fails if no WebSocket is connected
+        let _ = self
+            .broadcast
+            .send(serde_json::to_string(event).expect("Failed to serialize event"));
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// 128 bits from the random number generator of the operating system
+pub fn generate_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Accepts the token as `Authorization: Bearer <token>` or, for browser sources that can not set headers, as `?token=<token>`
+async fn authorize<B>(State(state): State<ApiState>, request: Request<B>, next: Next<B>) -> Response {
+    let header = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let query = request
+        .uri()
+        .query()
+        .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("token=")));
+    match header.or(query) {
+        Some(token) if constant_time_eq(token.as_bytes(), state.token.as_bytes()) => next.run(request).await,
+        _ => (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Missing or invalid token" }))).into_response()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn to_response(response: IpcResponse) -> Response {
+    match response {
+        IpcResponse::Ok => StatusCode::NO_CONTENT.into_response(),
+        IpcResponse::Status(status) => Json(status).into_response(),
+        IpcResponse::Profiles(profiles) => Json(profiles).into_response(),
+        IpcResponse::Settings(settings) => Json(settings).into_response(),
+        IpcResponse::Profile { index, name } => Json(json!({ "index": index, "name": name })).into_response(),
+        IpcResponse::Setting { setting, value } => Json(SettingEntry { setting, value }).into_response(),
+        IpcResponse::Error { code, message } => {
+            let status = match code {
+                EXIT_NO_DEVICE => StatusCode::SERVICE_UNAVAILABLE,
+                EXIT_INVALID_ARGUMENT => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR
+            };
+            (status, Json(json!({ "error": message }))).into_response()
+        }
+    }
+}
+
+async fn status(State(state): State<ApiState>) -> Response {
+    to_response(state.dispatch(IpcRequest::Status).await)
+}
+
+async fn profiles(State(state): State<ApiState>) -> Response {
+    to_response(state.dispatch(IpcRequest::ListProfiles).await)
+}
+
+#[derive(Debug, Deserialize)]
+struct SelectProfile {
+    /// A profile name or index
+    profile: String
+}
+
+async fn select_profile(State(state): State<ApiState>, Json(body): Json<SelectProfile>) -> Response {
+    to_response(state.dispatch(IpcRequest::SelectProfile(body.profile)).await)
+}
+
+async fn settings(State(state): State<ApiState>) -> Response {
+    to_response(state.dispatch(IpcRequest::ListSettings).await)
+}
+
+#[derive(Debug, Deserialize)]
+struct SetSetting {
+    /// Accepts the same values as the `set` command, as a string or as a JSON number or boolean
+    value: Value
+}
+
+async fn set_setting(State(state): State<ApiState>, Path(setting): Path<String>, Json(body): Json<SetSetting>) -> Response {
+    let value = match body.value {
+        Value::String(value) => value,
+        Value::Bool(true) => String::from("on"),
+        Value::Bool(false) => String::from("off"),
+        value => value.to_string()
+    };
+    to_response(state.dispatch(IpcRequest::SetSetting { setting, value }).await)
+}
+
+async fn events_socket(State(state): State<ApiState>, ws: WebSocketUpgrade) -> Response {
+    let receiver = state.broadcast.subscribe();
+    ws.on_upgrade(move |socket| forward_events(socket, receiver))
+}
+
+async fn forward_events(mut socket: WebSocket, mut receiver: broadcast::Receiver<String>) {
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => {
+                    if socket.send(Message::Text(event)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => tracing::debug!("A websocket client skipped {} events", skipped),
+                Err(RecvError::Closed) => break
+            },
+            message = socket.recv() => match message {
+                Some(Ok(_)) => {}
+                _ => break
+            }
+        }
+    }
+}
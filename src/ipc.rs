@@ -0,0 +1,295 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines, ReadHalf};
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::cli::{ProfileEntry, SettingEntry, Status};
+use crate::devices::{SettingId, SettingValue};
+use crate::{AppEvent, EventSender};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum IpcRequest {
+    ShowWindow,
+    Status,
+    ListProfiles,
+    ListSettings,
+    SelectProfile(String),
+    SetSetting { setting: String, value: String },
+    /// Like [IpcRequest::SetSetting] for callers that already know the setting
+    SetSettingById { id: SettingId, value: String },
+    /// `band` starts at 0
+    SetEqualizerBand { band: usize, level: u8 },
+    /// Answers with the current status and keeps sending it whenever it changes
+    Subscribe,
+    /// Answers once the changes requested so far were sent to the headset
+    Flush
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum IpcResponse {
+    Ok,
+    Status(Status),
+    Profiles(Vec<ProfileEntry>),
+    Settings(Vec<SettingEntry>),
+    Profile { index: usize, name: String },
+    Setting { setting: String, value: SettingValue },
+    Error { code: u8, message: String }
+}
+
+pub type IpcReply = oneshot::Sender<IpcResponse>;
+
+/// How long to wait for the running instance to answer a request
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(unix)]
+pub fn socket_path() -> std::io::Result<PathBuf> {
+    Ok(crate::config::runtime_dir()?.join("headset-controller.sock"))
+}
+
+#[cfg(windows)]
+pub fn socket_path() -> std::io::Result<PathBuf> {
+    Ok(PathBuf::from(r"\\.\pipe\headset-controller"))
+}
+
+/// Forwards a request to the running instance. Returns `None` if there is no running instance.
+///
+/// An error means that something listens on the socket but did not answer properly.
+#[instrument]
+pub async fn send(request: &IpcRequest) -> Result<Option<IpcResponse>> {
+    tokio::time::timeout(SEND_TIMEOUT, exchange(request))
+        .await
+        .map_err(|_| eyre!("The running instance did not answer within {:?}", SEND_TIMEOUT))?
+}
+
+async fn exchange(request: &IpcRequest) -> Result<Option<IpcResponse>> {
+    let Some(stream) = connect_running().await? else {
+        return Ok(None);
+    };
+    let (read, mut write) = tokio::io::split(stream);
+    write_line(&mut write, request).await?;
+    let mut response = String::new();
+    BufReader::new(read).read_line(&mut response).await?;
+    Ok(Some(serde_json::from_str(&response)?))
+}
+
+/// Subscribes to the status of the running instance. Returns `None` if there is no running instance.
+pub async fn subscribe() -> Result<Option<Subscription>> {
+    let Some(stream) = connect_running().await? else {
+        return Ok(None);
+    };
+    let (read, mut write) = tokio::io::split(stream);
+    write_line(&mut write, &IpcRequest::Subscribe).await?;
+    Ok(Some(Subscription {
+        lines: BufReader::new(read).lines()
+    }))
+}
+
+/// The answers to an [IpcRequest::Subscribe]
+pub struct Subscription {
+    lines: Lines<BufReader<ReadHalf<Stream>>>
+}
+
+impl Subscription {
+    /// Waits for the next status, `None` once the running instance is gone
+    pub async fn next(&mut self) -> Result<Option<IpcResponse>> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_str(&line)?)),
+            None => Ok(None)
+        }
+    }
+}
+
+async fn write_line<T: Serialize, W: AsyncWrite + Unpin>(write: &mut W, message: &T) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(message).expect("Failed to serialize message");
+    line.push('\n');
+    write.write_all(line.as_bytes()).await
+}
+
+async fn connect_running() -> Result<Option<Stream>> {
+    match connect().await {
+        Ok(stream) => Ok(Some(stream)),
+        Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => Ok(None),
+        Err(err) => Err(err.into())
+    }
+}
+
+#[cfg(unix)]
+type Stream = tokio::net::UnixStream;
+
+#[cfg(unix)]
+async fn connect() -> std::io::Result<Stream> {
+    tokio::net::UnixStream::connect(socket_path()?).await
+}
+
+#[cfg(windows)]
+type Stream = tokio::net::windows::named_pipe::NamedPipeClient;
+
+#[cfg(windows)]
+async fn connect() -> std::io::Result<Stream> {
+    tokio::net::windows::named_pipe::ClientOptions::new().open(socket_path()?)
+}
+
+pub struct IpcServer {
+    task: JoinHandle<()>,
+    updates: broadcast::Sender<IpcResponse>
+}
+
+impl IpcServer {
+    /// Must only be called after [send] confirmed that no other instance is running
+    pub fn start(runtime: &Runtime, events: EventSender) -> Result<Self> {
+        let _guard = runtime.enter();
+        let path = socket_path()?;
+        let (updates, _) = broadcast::channel(16);
+        let task = runtime.spawn(listen(bind()?, events, updates.clone()));
+        tracing::info!("Listening for commands on {}", path.display());
+        Ok(Self { task, updates })
+    }
+
+    /// Sends the status to every subscriber
+    pub fn publish(&self, status: Option<&Status>) {
+        let update = match status {
+            Some(status) => IpcResponse::Status(status.clone()),
+            None => crate::cli::no_device()
+        };
+        // Sending only fails if nobody is subscribed
+        let _ = self.updates.send(update);
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        #[cfg(unix)]
+        socket_path()
+            .and_then(std::fs::remove_file)
+            .unwrap_or_else(|err| tracing::warn!("Could not remove the socket: {}", err));
+    }
+}
+
+#[cfg(unix)]
+type Listener = tokio::net::UnixListener;
+
+#[cfg(unix)]
+fn bind() -> Result<Listener> {
+    let path = socket_path()?;
+    if path.exists() {
+        // The connection to this socket was refused, so it was left behind by a crashed instance
+        std::fs::remove_file(&path)?;
+    }
+    Ok(tokio::net::UnixListener::bind(path)?)
+}
+
+#[cfg(unix)]
+async fn listen(listener: Listener, events: EventSender, updates: broadcast::Sender<IpcResponse>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => _ = tokio::spawn(handle_connection(stream, events.clone(), updates.clone())),
+            Err(err) => {
+                tracing::error!("Could not accept ipc connection: {}", err);
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+type Listener = tokio::net::windows::named_pipe::NamedPipeServer;
+
+#[cfg(windows)]
+fn bind() -> Result<Listener> {
+    Ok(tokio::net::windows::named_pipe::ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(socket_path()?)?)
+}
+
+#[cfg(windows)]
+async fn listen(mut server: Listener, events: EventSender, updates: broadcast::Sender<IpcResponse>) {
+    use tokio::net::windows::named_pipe::ServerOptions;
+    loop {
+        if let Err(err) = server.connect().await {
+            tracing::error!("Could not accept ipc connection: {}", err);
+            break;
+        }
+        let next = match socket_path().and_then(|path| ServerOptions::new().create(path)) {
+            Ok(next) => next,
+            Err(err) => {
+                tracing::error!("Could not create the next pipe instance: {}", err);
+                break;
+            }
+        };
+        tokio::spawn(handle_connection(std::mem::replace(&mut server, next), events.clone(), updates.clone()));
+    }
+}
+
+async fn handle_connection<S: AsyncRead + AsyncWrite + Send + 'static>(stream: S, events: EventSender, updates: broadcast::Sender<IpcResponse>) {
+    let (read, mut write) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!("Could not read ipc request: {}", err);
+                break;
+            }
+        };
+        let response = match serde_json::from_str::<IpcRequest>(&line) {
+            Ok(IpcRequest::Subscribe) => {
+                stream_updates(&mut write, updates.subscribe(), events.clone()).await;
+                break;
+            }
+            Ok(request) => dispatch(request, events.clone()).await,
+            Err(err) => IpcResponse::Error {
+                code: 1,
+                message: format!("Invalid request: {}", err)
+            }
+        };
+        if let Err(err) = write_line(&mut write, &response).await {
+            tracing::warn!("Could not send ipc response: {}", err);
+            break;
+        }
+    }
+}
+
+async fn stream_updates<W: AsyncWrite + Unpin>(write: &mut W, mut updates: broadcast::Receiver<IpcResponse>, events: EventSender) {
+    let mut update = dispatch(IpcRequest::Status, events).await;
+    loop {
+        if write_line(write, &update).await.is_err() {
+            tracing::debug!("The subscriber is gone");
+            break;
+        }
+        update = loop {
+            match updates.recv().await {
+                Ok(update) => break update,
+                // Only the latest status matters
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return
+            }
+        };
+    }
+}
+
+/// Hands the request to the event loop and waits for the answer
+pub async fn dispatch(request: IpcRequest, events: EventSender) -> IpcResponse {
+    tracing::debug!("Received ipc request: {:?}", request);
+    let (sender, receiver) = oneshot::channel();
+    if events.send_event(AppEvent::Ipc(request, sender)).is_err() {
+        return IpcResponse::Error {
+            code: 1,
+            message: String::from("The application is shutting down")
+        };
+    }
+    receiver.await.unwrap_or_else(|_| IpcResponse::Error {
+        code: 1,
+        message: String::from("The request was dropped")
+    })
+}
@@ -39,25 +39,43 @@ fn main() -> Result<()> {
 }
 */
 
+mod alerts;
+mod app;
 mod audio;
+mod auto_profile;
+mod autoeq;
+mod cli;
 mod config;
+#[cfg(target_os = "linux")]
+mod dbus;
 mod debouncer;
 mod devices;
+mod dsp;
+mod hooks;
+mod http;
+mod ipc;
+mod midi;
+mod mqtt;
 mod notification;
+mod osc;
 mod renderer;
+mod schedule;
+mod scripts;
 mod tray;
 mod ui;
 mod util;
 
 use std::ops::Not;
-use std::sync::Mutex;
-use std::time::{Duration, Instant};
+use std::process::ExitCode;
+use std::sync::{mpsc, Mutex};
+use std::time::Instant;
 
+use clap::Parser;
 use color_eyre::Result;
 use tao::event::Event;
-use tao::event_loop::{ControlFlow, EventLoop};
+use tao::event_loop::{ControlFlow, EventLoop, EventLoopProxy, EventLoopWindowTarget};
 use tao::platform::run_return::EventLoopExtRunReturn;
-use tokio::runtime::Builder;
+use tokio::runtime::{Builder, Runtime};
 use tracing::instrument;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::filter::{LevelFilter, Targets};
@@ -65,16 +83,106 @@ use tracing_subscriber::fmt::layer;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+use crate::app::App;
 use crate::audio::AudioSystem;
-use crate::config::{log_file, Config, EqualizerConfig, HeadsetConfig, CLOSE_IMMEDIATELY, START_QUIET, PRINT_UDEV_RULES};
+use crate::cli::{Cli, Command, GuiArgs};
+use crate::config::{log_file, Config, HeadsetConfig, NoiseCancellingMode};
 use crate::debouncer::{Action, Debouncer};
-use crate::devices::{BatteryLevel, BoxedDevice, Device, DeviceManager, DeviceUpdate, generate_udev_rules};
+use crate::devices::{BatteryLevel, BoxedDevice, Device, DeviceUpdate, SettingId, SettingScope, SettingValue};
+use crate::ipc::{IpcReply, IpcRequest, IpcResponse};
+use crate::midi::MidiInput;
+use crate::notification::NotificationAction;
 use crate::renderer::EguiWindow;
-use crate::tray::{AppTray, TrayEvent};
+use crate::tray::{AppTray, TrayEvent, TrayMenuEntries};
+use crate::util::SenderExt;
 
-fn main() -> Result<()> {
-    if *PRINT_UDEV_RULES { return Ok(println!("{}", generate_udev_rules()?)); }
+#[derive(Debug)]
+pub enum AppEvent {
+    Device(DeviceUpdate),
+    Ipc(IpcRequest, IpcReply),
+    Midi(MidiInput),
+    Notification(NotificationAction),
+    /// Rescans the devices, e.g. after the udev rules changed
+    RefreshDevices,
+    /// A StatusNotifier host appeared, so the tray icon can be created now
+    #[cfg(target_os = "linux")]
+    TrayHostAvailable,
+    Shutdown
+}
+
+impl From<DeviceUpdate> for AppEvent {
+    fn from(value: DeviceUpdate) -> Self {
+        Self::Device(value)
+    }
+}
+
+/// Delivers [AppEvent]s to the tao event loop or, when running without a window system, to a plain channel
+#[derive(Debug, Clone)]
+pub enum EventSender {
+    EventLoop(EventLoopProxy<AppEvent>),
+    Channel(mpsc::Sender<AppEvent>)
+}
+
+impl EventSender {
+    pub fn send_event(&self, event: AppEvent) -> Result<(), AppEvent> {
+        match self {
+            EventSender::EventLoop(proxy) => proxy.send_event(event).map_err(|err| err.0),
+            EventSender::Channel(sender) => sender.send(event).map_err(|err| err.0)
+        }
+    }
+}
+
+/// An [EventSender] for services that share it between threads, the event loop proxy is not guaranteed to be `Sync` on every platform
+#[derive(Debug)]
+pub struct SyncEventSender(Mutex<EventSender>);
+
+impl SyncEventSender {
+    pub fn new(events: EventSender) -> Self {
+        Self(Mutex::new(events))
+    }
+
+    pub fn get(&self) -> EventSender {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+fn main() -> Result<ExitCode> {
+    let mut cli = Cli::parse();
+    if cli.print_udev_rules {
+        cli.command = Some(Command::UdevRules);
+    }
     color_eyre::install()?;
+    match cli.command {
+        None => run_gui(&cli.gui, cli.dummy_device).map(|_| ExitCode::SUCCESS),
+        Some(Command::Gui(ref args)) => run_gui(args, cli.dummy_device).map(|_| ExitCode::SUCCESS),
+        Some(Command::Daemon) => run_daemon(cli.dummy_device).map(|_| ExitCode::SUCCESS),
+        Some(_) => {
+            tracing_subscriber::registry()
+                .with(ErrorLayer::default())
+                .with(Targets::new().with_default(LevelFilter::WARN))
+                .with(layer().without_time().with_writer(std::io::stderr))
+                .init();
+            cli::run(&cli)
+        }
+    }
+}
+
+fn already_running(runtime: &Runtime, request: IpcRequest) -> bool {
+    match runtime.block_on(ipc::send(&request)) {
+        Ok(None) => false,
+        Ok(Some(_)) => {
+            println!("Headset Controller is already running");
+            true
+        }
+        Err(err) => {
+            // Starting anyway would take the socket away from the instance that is still holding it
+            eprintln!("Another instance seems to be running but does not answer: {}", err);
+            true
+        }
+    }
+}
+
+fn init_logging() {
     let logfile = Mutex::new(log_file());
     tracing_subscriber::registry()
         .with(ErrorLayer::default())
@@ -82,171 +190,127 @@ fn main() -> Result<()> {
         .with(layer().without_time())
         .with(layer().with_ansi(false).with_writer(logfile))
         .init();
+}
+
+fn run_daemon(dummy_device: bool) -> Result<()> {
     let runtime = Builder::new_multi_thread().enable_all().build()?;
+    if already_running(&runtime, IpcRequest::Status) {
+        return Ok(());
+    }
+    init_logging();
 
     let span = tracing::info_span!("init").entered();
+    let (sender, receiver) = mpsc::channel();
+    let events = EventSender::Channel(sender);
+    let app = App::new(runtime, events.clone(), dummy_device)?;
+    app.runtime.spawn(async move {
+        app::shutdown_signal().await;
+        tracing::info!("Received shutdown signal");
+        events.send_log(AppEvent::Shutdown);
+    });
+    span.exit();
 
-    let mut config = Config::load()?;
-
-    let mut event_loop = EventLoop::with_user_event();
-    let event_loop_proxy = event_loop.create_proxy();
-
-    let mut audio_system = AudioSystem::new();
+    app.run_headless(receiver);
+    Ok(())
+}
 
-    let mut device_manager = runtime.block_on(DeviceManager::new())?;
-    let mut device = runtime.block_on(async {
-        device_manager
-            .find_preferred_device(&config.preferred_device, event_loop_proxy.clone())
-            .await
-    });
+fn run_gui(args: &GuiArgs, dummy_device: bool) -> Result<()> {
+    let runtime = Builder::new_multi_thread().enable_all().build()?;
+    let request = match args.quiet {
+        true => IpcRequest::Status,
+        false => IpcRequest::ShowWindow
+    };
+    if already_running(&runtime, request) {
+        return Ok(());
+    }
+    init_logging();
 
-    let mut tray = AppTray::new(&event_loop);
+    let span = tracing::info_span!("init").entered();
 
-    let mut window: Option<EguiWindow> = START_QUIET.not().then(|| EguiWindow::new(&event_loop));
+    let mut event_loop = EventLoop::with_user_event();
+    let events = EventSender::EventLoop(event_loop.create_proxy());
+    let mut app = App::new(runtime, events.clone(), dummy_device)?;
+    app.tray = create_tray(&app.runtime, &events, &event_loop);
 
-    let mut debouncer = Debouncer::new();
-    let mut last_connected = false;
-    let mut last_battery = Default::default();
-    debouncer.submit_all([Action::UpdateSystemAudio, Action::UpdateTrayTooltip, Action::UpdateTray]);
+    // Without a tray the window is the only way to interact with the application
+    let show_window = args.quiet.not() || app.tray.is_none();
+    let mut window: Option<EguiWindow> = show_window.then(|| EguiWindow::new(&event_loop));
 
     span.exit();
     event_loop.run_return(move |event, event_loop, control_flow| {
+        let mut tray_event = None;
         if window
             .as_mut()
             .map(|w| {
-                w.handle_events(&event, |egui_ctx| match &device {
-                    Some(device) => ui::config_ui(
-                        egui_ctx,
-                        &mut debouncer,
-                        &mut config,
-                        device.as_ref(),
-                        device_manager.supported_devices(),
-                        &mut audio_system
-                    ),
-                    None => ui::no_device_ui(egui_ctx, &mut debouncer)
+                w.handle_events(&event, |egui_ctx| {
+                    if app.tray.is_none() {
+                        let entries = tray_menu_entries(&mut app.config, app.device.as_deref());
+                        tray_event = ui::tray_fallback_ui(egui_ctx, &entries);
+                    }
+                    match &app.device {
+                        Some(device) => ui::config_ui(
+                            egui_ctx,
+                            &mut app.debouncer,
+                            &mut app.config,
+                            device.as_ref(),
+                            app.device_manager.supported_devices(),
+                            &mut app.audio_system
+                        ),
+                        None => ui::no_device_ui(egui_ctx, &mut app.debouncer)
+                    }
                 })
             })
             .unwrap_or(false)
         {
-            debouncer.force(Action::SaveConfig);
+            app.debouncer.force(Action::SaveConfig);
             window.take();
-            if *CLOSE_IMMEDIATELY {
+            if args.close_on_quit || app.tray.is_none() {
                 *control_flow = ControlFlow::Exit;
             }
         }
+        match tray_event {
+            Some(TrayEvent::Quit) => *control_flow = ControlFlow::Exit,
+            Some(event) => app.handle_tray_event(event),
+            None => {}
+        }
 
         match event {
             Event::MenuEvent { menu_id, .. } => {
                 let _span = tracing::info_span!("tray_menu_event").entered();
-                match tray.handle_event(menu_id) {
-                    Some(TrayEvent::Open) => {
-                        audio_system.refresh_devices();
-                        match &mut window {
-                            None => window = Some(EguiWindow::new(event_loop)),
-                            Some(window) => {
-                                window.focus();
-                            }
-                        }
-                    }
+                match app.tray.as_mut().and_then(|tray| tray.handle_event(menu_id)) {
+                    Some(TrayEvent::Open) => open_window(&mut window, event_loop, &mut app.audio_system),
                     Some(TrayEvent::Quit) => {
                         *control_flow = ControlFlow::Exit;
                     }
-                    Some(TrayEvent::Profile(id)) => {
-                        let _span = tracing::info_span!("profile_change", id).entered();
-                        if let Some(device) = &device {
-                            let headset = config.get_headset(device.name());
-                            if id as u32 != headset.selected_profile_index {
-                                let len = headset.profiles.len();
-                                if id < len {
-                                    headset.selected_profile_index = id as u32;
-                                    submit_profile_change(&mut debouncer);
-                                    debouncer.submit_all([Action::SaveConfig, Action::UpdateTray]);
-                                } else {
-                                    tracing::warn!(len, "Profile id out of range")
-                                }
-                            } else {
-                                tracing::trace!("Profile already selected");
-                            }
-                        }
-                    }
-                    _ => {}
+                    Some(event) => app.handle_tray_event(event),
+                    None => {}
                 }
             }
-            Event::NewEvents(_) | Event::LoopDestroyed => {
-                while let Some(action) = debouncer.next() {
-                    let _span = tracing::info_span!("debouncer_event", ?action).entered();
-                    tracing::trace!("Processing event");
-                    match action {
-                        Action::UpdateDeviceStatus => {
-                            if let Some(device) = &device {
-                                let current_connection = device.is_connected();
-                                let current_battery = device.get_battery_status();
-                                if current_connection != last_connected {
-                                    let msg = build_notification_text(current_connection, &[current_battery, last_battery]);
-                                    notification::notify(device.name(), &msg, Duration::from_secs(2))
-                                        .unwrap_or_else(|err| tracing::warn!("Can not create notification: {:?}", err));
-                                    debouncer.submit_all([Action::UpdateSystemAudio, Action::UpdateTrayTooltip]);
-                                    debouncer.force(Action::UpdateSystemAudio);
-                                    last_connected = current_connection;
-                                }
-                                if last_battery != current_battery {
-                                    debouncer.submit(Action::UpdateTrayTooltip);
-                                    last_battery = current_battery;
-                                }
-                            }
-                        }
-                        Action::RefreshDeviceList => runtime.block_on(async {
-                            device = None;
-                            device_manager
-                                .refresh()
-                                .await
-                                .unwrap_or_else(|err| tracing::warn!("Failed to refresh devices: {}", err))
-                        }),
-                        Action::SwitchDevice => {
-                            if config.preferred_device != device.as_ref().map(|d| d.name().to_string()) {
-                                device = runtime.block_on(async {
-                                    device_manager
-                                        .find_preferred_device(&config.preferred_device, event_loop_proxy.clone())
-                                        .await
-                                });
-                                submit_full_change(&mut debouncer);
-                                debouncer.submit_all([Action::UpdateTray, Action::UpdateTrayTooltip]);
-                            } else {
-                                tracing::debug!("Preferred device is already active")
-                            }
-                        }
-                        Action::UpdateSystemAudio => {
-                            if let Some(device) = &device {
-                                let headset = config.get_headset(device.name());
-                                audio_system.apply(&headset.os_audio, device.is_connected())
-                            }
-                        }
-                        Action::SaveConfig => {
-                            config
-                                .save()
-                                .unwrap_or_else(|err| tracing::warn!("Could not save config: {:?}", err));
-                        }
-                        Action::UpdateTray => update_tray(&mut tray, &mut config, device.as_ref().map(|d| d.name())),
-                        Action::UpdateTrayTooltip => update_tray_tooltip(&mut tray, &device),
-                        action => {
-                            if let Some(device) = &device {
-                                let headset = config.get_headset(device.name());
-                                apply_config_to_device(action, device.as_ref(), headset)
-                            }
-                        }
-                    }
-                }
+            Event::NewEvents(_) | Event::LoopDestroyed => app.process_actions(),
+            Event::UserEvent(AppEvent::Device(update)) => app.handle_device_update(update),
+            Event::UserEvent(AppEvent::Ipc(IpcRequest::ShowWindow, reply)) => {
+                open_window(&mut window, event_loop, &mut app.audio_system);
+                reply
+                    .send(IpcResponse::Ok)
+                    .unwrap_or_else(|_| tracing::warn!("Could not send the ipc response"));
+            }
+            Event::UserEvent(AppEvent::Ipc(request, reply)) => app.handle_ipc(request, reply),
+            Event::UserEvent(AppEvent::Midi(input)) => app.handle_midi_input(input),
+            Event::UserEvent(AppEvent::Notification(NotificationAction::OpenSettings)) => {
+                open_window(&mut window, event_loop, &mut app.audio_system)
+            }
+            Event::UserEvent(AppEvent::Notification(action)) => app.handle_notification_action(action),
+            Event::UserEvent(AppEvent::RefreshDevices) => app.refresh_devices(),
+            #[cfg(target_os = "linux")]
+            Event::UserEvent(AppEvent::TrayHostAvailable) => attach_tray(&mut app, event_loop),
+            Event::UserEvent(AppEvent::Shutdown) => {
+                *control_flow = ControlFlow::Exit;
             }
-            Event::UserEvent(event) => match event {
-                DeviceUpdate::ConnectionChanged | DeviceUpdate::BatteryLevel => debouncer.submit(Action::UpdateDeviceStatus),
-                DeviceUpdate::DeviceError(err) => tracing::error!("The device return an error: {}", err),
-                DeviceUpdate::ChatMixChanged => {}
-            },
             _ => ()
         }
         if !matches!(*control_flow, ControlFlow::ExitWithCode(_)) {
             let next_window_update = window.as_ref().and_then(|w| w.next_repaint());
-            let next_update = [next_window_update, debouncer.next_action()]
+            let next_update = [next_window_update, app.next_action()]
                 .into_iter()
                 .flatten()
                 .min();
@@ -262,110 +326,152 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Creates the tray icon, on Linux only if a StatusNotifier host is running
+fn create_tray(runtime: &Runtime, events: &EventSender, event_loop: &EventLoopWindowTarget<AppEvent>) -> Option<AppTray> {
+    if !tray_host_available(runtime, events) {
+        return None;
+    }
+    AppTray::new(event_loop)
+        .map_err(|err| tracing::warn!("Could not create the tray icon, keeping the window open instead: {:?}", err))
+        .ok()
+}
+
+/// Without a host a [AppEvent::TrayHostAvailable] is sent once one appears
+#[cfg(target_os = "linux")]
+fn tray_host_available(runtime: &Runtime, events: &EventSender) -> bool {
+    match runtime.block_on(tray::host_available()) {
+        Ok(true) => true,
+        Ok(false) => {
+            tracing::warn!("No StatusNotifier host is running, keeping the window open until one appears");
+            let events = events.clone();
+            runtime.spawn(async move {
+                match tray::wait_for_host().await {
+                    Ok(()) => _ = events.send_event(AppEvent::TrayHostAvailable),
+                    Err(err) => tracing::warn!("Could not watch for a StatusNotifier host: {:?}", err)
+                }
+            });
+            false
+        }
+        Err(err) => {
+            tracing::warn!("Could not look for a StatusNotifier host, keeping the window open instead: {:?}", err);
+            false
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn tray_host_available(_runtime: &Runtime, _events: &EventSender) -> bool {
+    true
+}
+
+/// Creates the tray icon for a StatusNotifier host that started after the application
+#[cfg(target_os = "linux")]
+fn attach_tray(app: &mut App, event_loop: &EventLoopWindowTarget<AppEvent>) {
+    match AppTray::new(event_loop) {
+        Ok(tray) => {
+            tracing::info!("A tray became available");
+            app.tray = Some(tray);
+            app.debouncer
+                .submit_all([Action::UpdateTray, Action::UpdateTrayTooltip]);
+            app.debouncer
+                .force_all([Action::UpdateTray, Action::UpdateTrayTooltip]);
+        }
+        Err(err) => tracing::warn!("Could not create the tray icon: {:?}", err)
+    }
+}
+
+fn open_window(window: &mut Option<EguiWindow>, event_loop: &EventLoopWindowTarget<AppEvent>, audio_system: &mut AudioSystem) {
+    audio_system.refresh_devices();
+    match window {
+        None => *window = Some(EguiWindow::new(event_loop)),
+        Some(window) => {
+            window.focus();
+        }
+    }
+}
+
+#[instrument(skip(debouncer, config, device))]
+fn select_profile(debouncer: &mut Debouncer, config: &mut Config, device: &dyn Device, id: usize) {
+    let headset = config.get_headset(device.name());
+    if id as u32 != headset.selected_profile_index {
+        let len = headset.profiles.len();
+        if id < len {
+            headset.selected_profile_index = id as u32;
+            submit_profile_change(debouncer, device);
+            debouncer.submit_all([Action::SaveConfig, Action::UpdateTray]);
+        } else {
+            tracing::warn!(len, "Profile id out of range")
+        }
+    } else {
+        tracing::trace!("Profile already selected");
+    }
+}
+
 #[instrument(skip_all)]
-fn submit_profile_change(debouncer: &mut Debouncer) {
-    let actions = [
-        Action::UpdateSideTone,
-        Action::UpdateEqualizer,
-        Action::UpdateMicrophoneVolume,
-        Action::UpdateVolumeLimit
-    ];
-    debouncer.submit_all(actions);
+fn submit_profile_change(debouncer: &mut Debouncer, device: &dyn Device) {
+    let actions = device
+        .settings()
+        .into_iter()
+        .filter(|setting| setting.scope == SettingScope::Profile)
+        .map(|setting| Action::UpdateSetting(setting.id))
+        .chain([Action::UpdateSoftwareEqualizer, Action::UpdateMicrophoneChain, Action::PublishStatus])
+        .collect::<Vec<_>>();
+    debouncer.submit_all(actions.iter().copied());
     debouncer.force_all(actions);
 }
 
 #[instrument(skip_all)]
-fn submit_full_change(debouncer: &mut Debouncer) {
-    submit_profile_change(debouncer);
-    let actions = [
-        Action::UpdateMicrophoneLight,
-        Action::UpdateInactiveTime,
-        Action::UpdateBluetoothCall,
-        Action::UpdateAutoBluetooth,
-        Action::UpdateSystemAudio
-    ];
-    debouncer.submit_all(actions);
+fn submit_full_change(debouncer: &mut Debouncer, device: &dyn Device) {
+    let actions = device
+        .settings()
+        .into_iter()
+        .map(|setting| Action::UpdateSetting(setting.id))
+        .chain([Action::UpdateSystemAudio, Action::UpdateSoftwareEqualizer, Action::UpdateMicrophoneChain])
+        .collect::<Vec<_>>();
+    debouncer.submit_all(actions.iter().copied());
     debouncer.force_all(actions);
 }
 
 #[instrument(skip_all, fields(name = %device.name()))]
-fn apply_config_to_device(action: Action, device: &dyn Device, headset: &mut HeadsetConfig) {
+fn apply_config_to_device(id: SettingId, device: &dyn Device, headset: &mut HeadsetConfig) {
     if device.is_connected() {
-        match action {
-            Action::UpdateSideTone => {
-                if let Some(sidetone) = device.get_side_tone() {
-                    let _span = tracing::info_span!("sidetone").entered();
-                    sidetone.set_level(headset.selected_profile().side_tone);
-                }
-            }
-            Action::UpdateEqualizer => {
-                if let Some(equalizer) = device.get_equalizer() {
-                    let _span = tracing::info_span!("equalizer").entered();
-                    let levels = match headset.selected_profile().equalizer.clone() {
-                        EqualizerConfig::Preset(i) => equalizer
-                            .presets()
-                            .get(i as usize)
-                            .expect("Unknown preset")
-                            .1
-                            .to_vec(),
-                        EqualizerConfig::Custom(levels) => levels
-                    };
-                    equalizer.set_levels(&levels);
-                }
-            }
-            Action::UpdateMicrophoneVolume => {
-                if let Some(mic_volume) = device.get_mic_volume() {
-                    let _span = tracing::info_span!("mic_volume").entered();
-                    mic_volume.set_level(headset.selected_profile().microphone_volume);
-                }
-            }
-            Action::UpdateVolumeLimit => {
-                if let Some(volume_limiter) = device.get_volume_limiter() {
-                    let _span = tracing::info_span!("volume_limiter").entered();
-                    volume_limiter.set_enabled(headset.selected_profile().volume_limiter);
-                }
-            }
-            Action::UpdateInactiveTime => {
-                if let Some(inactive_time) = device.get_inactive_time() {
-                    let _span = tracing::info_span!("inactive time").entered();
-                    inactive_time.set_inactive_time(headset.inactive_time);
-                }
-            }
-            Action::UpdateMicrophoneLight => {
-                if let Some(mic_light) = device.get_mic_light() {
-                    let _span = tracing::info_span!("mic_light").entered();
-                    mic_light.set_light_strength(headset.mic_light);
-                }
+        match device.settings().into_iter().find(|setting| setting.id == id) {
+            Some(setting) => {
+                let _span = tracing::info_span!("setting", label = setting.label).entered();
+                let value = headset.resolved_setting(&setting);
+                device.apply_setting(id, &value);
             }
-            Action::UpdateAutoBluetooth => {
-                if let Some(bluetooth_config) = device.get_bluetooth_config() {
-                    let _span = tracing::info_span!("bluetooth").entered();
-                    bluetooth_config.set_auto_enabled(headset.auto_enable_bluetooth);
-                }
-            }
-            Action::UpdateBluetoothCall => {
-                if let Some(bluetooth_config) = device.get_bluetooth_config() {
-                    let _span = tracing::info_span!("bluetooth").entered();
-                    bluetooth_config.set_call_action(headset.bluetooth_call);
-                }
-            }
-            _ => tracing::warn!("{:?} is not related to the device", action)
+            None => tracing::warn!("{:?} is not supported by the device", id)
         }
     }
 }
 
 #[instrument(skip_all)]
-pub fn update_tray(tray: &mut AppTray, config: &mut Config, device_name: Option<&str>) {
-    match device_name {
-        None => {
-            tray.build_menu(0, |_| ("", false));
-        }
-        Some(device_name) => {
-            let headset = config.get_headset(device_name);
-            let selected = headset.selected_profile_index as usize;
-            let profiles = &headset.profiles;
-            tray.build_menu(profiles.len(), |id| (profiles[id].name.as_str(), id == selected));
-        }
+pub fn update_tray(tray: &mut AppTray, config: &mut Config, device: Option<&dyn Device>) {
+    tray.build_menu(&tray_menu_entries(config, device));
+}
+
+fn tray_menu_entries(config: &mut Config, device: Option<&dyn Device>) -> TrayMenuEntries {
+    let Some(device) = device else {
+        return TrayMenuEntries::default();
+    };
+    let headset = config.get_headset(device.name());
+    let noise_cancelling = device
+        .settings()
+        .into_iter()
+        .find(|setting| setting.id == SettingId::NoiseCancelling)
+        .and_then(|setting| match headset.setting(&setting) {
+            SettingValue::Choice(choice) => NoiseCancellingMode::MODES.get(*choice as usize).copied(),
+            _ => None
+        });
+    TrayMenuEntries {
+        profiles: headset
+            .profiles
+            .iter()
+            .map(|profile| profile.name.clone())
+            .collect(),
+        selected: headset.selected_profile_index as usize,
+        noise_cancelling
     }
 }
 
@@ -391,11 +497,8 @@ pub fn update_tray_tooltip(tray: &mut AppTray, device: &Option<BoxedDevice>) {
     tracing::trace!("Updated tooltip");
 }
 
-fn build_notification_text(connected: bool, battery_levels: &[Option<BatteryLevel>]) -> String {
-    let msg = match connected {
-        true => "Connected",
-        false => "Disconnected"
-    };
+/// The battery level shown in connection notifications, the device might already report it as unknown
+fn lowest_battery_level(battery_levels: &[Option<BatteryLevel>]) -> Option<u8> {
     battery_levels
         .iter()
         .filter_map(|b| match b {
@@ -403,6 +506,4 @@ fn build_notification_text(connected: bool, battery_levels: &[Option<BatteryLeve
             _ => None
         })
         .min()
-        .map(|level| format!("{} (Battery: {}%)", msg, level))
-        .unwrap_or_else(|| msg.to_string())
 }
@@ -0,0 +1,132 @@
+use crate::config::{MidiControl, MidiTarget};
+use crate::devices::{Device, SettingKind};
+use crate::ipc::IpcRequest;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MidiInput {
+    pub control: MidiControl,
+    /// 0 to 127, note off is reported as 0
+    pub value: u8
+}
+
+/// Scales the 7-bit MIDI value to the range of the target
+pub fn request(target: MidiTarget, value: u8, device: &dyn Device) -> Option<IpcRequest> {
+    let scale = |min: u8, max: u8| min + ((value.min(127) as u32 * (max - min) as u32 + 63) / 127) as u8;
+    match target {
+        MidiTarget::Setting(id) => {
+            let setting = device.settings().into_iter().find(|s| s.id == id)?;
+            let value = match setting.kind {
+                SettingKind::Slider { min, max } => scale(min, max).to_string(),
+                SettingKind::Toggle => String::from(if value >= 64 { "on" } else { "off" }),
+                SettingKind::Enum { options } => scale(0, options.len().saturating_sub(1) as u8).to_string(),
+                SettingKind::Bands { .. } => return None
+            };
+            Some(IpcRequest::SetSettingById { id, value })
+        }
+        MidiTarget::EqualizerBand(band) => {
+            let equalizer = device.get_equalizer()?;
+            let min = equalizer.base_level() - equalizer.variance();
+            let max = equalizer.base_level() + equalizer.variance();
+            Some(IpcRequest::SetEqualizerBand {
+                band: band as usize,
+                level: scale(min, max)
+            })
+        }
+        // Buttons select the profile when pressed and ignore the release
+        MidiTarget::Profile(name) => (value > 0).then_some(IpcRequest::SelectProfile(name))
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use alsa_input::MidiListener;
+
+#[cfg(target_os = "linux")]
+mod alsa_input {
+    use std::ffi::CString;
+    use std::thread::JoinHandle;
+
+    use alsa::seq::{EvCtrl, EvNote, Event, EventType, PortCap, PortType, Seq};
+    use alsa::Direction;
+    use color_eyre::Result;
+
+    use super::MidiInput;
+    use crate::config::MidiControl;
+    use crate::{AppEvent, EventSender};
+
+    /// A writable ALSA sequencer port, connect controllers to it with `aconnect`
+    pub struct MidiListener {
+        // Blocks on the sequencer until the application exits
+        _thread: JoinHandle<()>
+    }
+
+    impl MidiListener {
+        pub fn start(events: EventSender) -> Result<Self> {
+            let seq = Seq::open(None, Some(Direction::Capture), false)?;
+            seq.set_client_name(&CString::new("Headset Controller")?)?;
+            let port = seq.create_simple_port(
+                &CString::new("Control")?,
+                PortCap::WRITE | PortCap::SUBS_WRITE,
+                PortType::MIDI_GENERIC | PortType::APPLICATION
+            )?;
+            tracing::info!("Listening for midi events on {}:{}", seq.client_id()?, port);
+            let thread = std::thread::Builder::new()
+                .name(String::from("midi"))
+                .spawn(move || listen(seq, events))?;
+            Ok(Self { _thread: thread })
+        }
+    }
+
+    fn listen(seq: Seq, events: EventSender) {
+        let mut input = seq.input();
+        loop {
+            match input.event_input() {
+                Ok(event) => {
+                    if let Some(input) = parse_event(&event) {
+                        if events.send_event(AppEvent::Midi(input)).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!("Could not read midi event: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn parse_event(event: &Event) -> Option<MidiInput> {
+        match event.get_type() {
+            EventType::Controller => event
+                .get_data::<EvCtrl>()
+                .map(|ctrl| MidiInput {
+                    control: MidiControl::ControlChange {
+                        channel: ctrl.channel,
+                        controller: ctrl.param
+                    },
+                    value: ctrl.value.clamp(0, 127) as u8
+                }),
+            EventType::Noteon | EventType::Noteoff => event.get_data::<EvNote>().map(|note| MidiInput {
+                control: MidiControl::Note {
+                    channel: note.channel,
+                    note: note.note
+                },
+                value: match event.get_type() {
+                    EventType::Noteon => note.velocity,
+                    _ => 0
+                }
+            }),
+            _ => None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct MidiListener;
+
+#[cfg(not(target_os = "linux"))]
+impl MidiListener {
+    pub fn start(_: crate::EventSender) -> color_eyre::Result<Self> {
+        color_eyre::eyre::bail!("Midi input is only supported on Linux")
+    }
+}
@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{json, Value};
+use tokio::runtime::Runtime;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::cli::Status;
+use crate::config::MqttConfig;
+use crate::ipc::{dispatch, IpcRequest, IpcResponse};
+use crate::EventSender;
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+struct State {
+    status: Option<Status>,
+    profiles: Vec<String>
+}
+
+/// Mirrors the headset state to retained topics below the configured prefix and accepts profile changes on `<prefix>/profile/set`
+pub struct MqttPublisher {
+    state: watch::Sender<State>,
+    task: JoinHandle<()>
+}
+
+impl MqttPublisher {
+    pub fn start(runtime: &Runtime, config: &MqttConfig, events: EventSender) -> Self {
+        let topics = Topics::new(config);
+        let mut options = MqttOptions::new(topics.node_id.clone(), config.host.clone(), config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(topics.availability(), "offline", QoS::AtLeastOnce, true));
+        if let Some(username) = &config.username {
+            options.set_credentials(username.clone(), config.password.clone().unwrap_or_default());
+        }
+        let (client, event_loop) = AsyncClient::new(options, 64);
+        let (state, receiver) = watch::channel(State::default());
+        let task = runtime.spawn(run(client, event_loop, topics, receiver, events));
+        tracing::info!("Publishing to mqtt://{}:{}", config.host, config.port);
+        Self { state, task }
+    }
+
+    /// `profiles` are the names offered by the profile select entity
+    pub fn update(&self, status: Option<Status>, profiles: Vec<String>) {
+        self.state.send_replace(State { status, profiles });
+    }
+}
+
+impl Drop for MqttPublisher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Topics {
+    prefix: String,
+    discovery_prefix: Option<String>,
+    node_id: String
+}
+
+impl Topics {
+    fn new(config: &MqttConfig) -> Self {
+        let prefix = config.topic_prefix.trim_end_matches('/').to_string();
+        let node_id = prefix
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        Self {
+            prefix,
+            discovery_prefix: config.discovery_prefix.clone(),
+            node_id
+        }
+    }
+
+    fn state(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix, name)
+    }
+
+    fn availability(&self) -> String {
+        self.state("availability")
+    }
+
+    fn profile_command(&self) -> String {
+        self.state("profile/set")
+    }
+
+    fn discovery(&self, component: &str, name: &str) -> Option<String> {
+        self.discovery_prefix
+            .as_ref()
+            .map(|prefix| format!("{}/{}/{}/{}/config", prefix, component, self.node_id, name))
+    }
+}
+
+async fn run(client: AsyncClient, mut event_loop: EventLoop, topics: Topics, mut state: watch::Receiver<State>, events: EventSender) {
+    // What the broker currently has retained, only known while connected
+    let mut published: Option<State> = None;
+    let mut connected = false;
+    loop {
+        tokio::select! {
+            event = event_loop.poll() => match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    tracing::debug!("Connected to the mqtt broker");
+                    connected = true;
+                    try_publish(&client, topics.availability(), "online".into());
+                    client
+                        .try_subscribe(topics.profile_command(), QoS::AtLeastOnce)
+                        .unwrap_or_else(|err| tracing::warn!("Could not subscribe to the command topic: {}", err));
+                    let current = state.borrow().clone();
+                    publish(&client, &topics, None, &current);
+                    published = Some(current);
+                }
+                Ok(Event::Incoming(Packet::Publish(message))) if message.topic == topics.profile_command() => {
+                    let profile = String::from_utf8_lossy(&message.payload).trim().to_string();
+                    let events = events.clone();
+                    tokio::spawn(async move {
+                        if let IpcResponse::Error { message, .. } = dispatch(IpcRequest::SelectProfile(profile), events).await {
+                            tracing::warn!("Could not select the profile requested over mqtt: {}", message);
+                        }
+                    });
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    if connected {
+                        tracing::warn!("Lost the connection to the mqtt broker: {}", err);
+                    }
+                    connected = false;
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            },
+            changed = state.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let current = state.borrow_and_update().clone();
+                if connected {
+                    publish(&client, &topics, published.as_ref(), &current);
+                    published = Some(current);
+                }
+            }
+        }
+    }
+}
+
+fn try_publish(client: &AsyncClient, topic: String, payload: String) {
+    client
+        .try_publish(topic, QoS::AtLeastOnce, true, payload)
+        .unwrap_or_else(|err| tracing::warn!("Could not publish mqtt message: {}", err));
+}
+
+fn payloads(state: &State) -> [(&'static str, String); 6] {
+    let on_off = |value: bool| String::from(if value { "ON" } else { "OFF" });
+    let status = state.status.as_ref();
+    [
+        ("device", status.map_or_else(String::new, |s| s.device.clone())),
+        ("connected", on_off(status.map_or(false, |s| s.connected))),
+        ("battery", status.and_then(|s| s.battery).map_or_else(String::new, |level| level.to_string())),
+        ("charging", on_off(status.map_or(false, |s| s.charging))),
+        ("chat_mix", status.and_then(|s| s.chat_mix).map_or_else(String::new, |mix| json!(mix).to_string())),
+        ("profile", status.map_or_else(String::new, |s| s.profile.clone()))
+    ]
+}
+
+/// Only publishes the topics that differ from `old`
+fn publish(client: &AsyncClient, topics: &Topics, old: Option<&State>, new: &State) {
+    let old_payloads = old.map(payloads);
+    for (i, (name, payload)) in payloads(new).into_iter().enumerate() {
+        if old_payloads
+            .as_ref()
+            .map_or(true, |old| old[i].1 != payload)
+        {
+            try_publish(client, topics.state(name), payload);
+        }
+    }
+
+    let device = |s: &State| s.status.as_ref().map(|s| s.device.clone());
+    if old.map_or(true, |old| device(old) != device(new) || old.profiles != new.profiles) {
+        for (topic, payload) in discovery(topics, new) {
+            try_publish(client, topic, payload.to_string());
+        }
+    }
+}
+
+/// The Home Assistant discovery messages for every entity
+fn discovery(topics: &Topics, state: &State) -> Vec<(String, Value)> {
+    let device = json!({
+        "identifiers": [topics.node_id],
+        "name": state.status.as_ref().map_or("Headset", |s| s.device.as_str()),
+        "manufacturer": "Headset Controller"
+    });
+    let entity = |name: &str, state_topic: String, extra: Value| {
+        let mut payload = json!({
+            "name": name,
+            "unique_id": format!("{}_{}", topics.node_id, name.to_lowercase().replace(' ', "_")),
+            "state_topic": state_topic,
+            "availability_topic": topics.availability(),
+            "device": device
+        });
+        if let (Value::Object(payload), Value::Object(extra)) = (&mut payload, extra) {
+            payload.extend(extra);
+        }
+        payload
+    };
+    let entities = [
+        ("binary_sensor", "connected", entity("Connected", topics.state("connected"), json!({ "device_class": "connectivity" }))),
+        ("binary_sensor", "charging", entity("Charging", topics.state("charging"), json!({ "device_class": "battery_charging" }))),
+        (
+            "sensor",
+            "battery",
+            entity(
+                "Battery",
+                topics.state("battery"),
+                json!({
+                    "device_class": "battery",
+                    "unit_of_measurement": "%",
+                    "value_template": "{{ value if value | is_number else None }}"
+                })
+            )
+        ),
+        (
+            "sensor",
+            "game_volume",
+            entity(
+                "Game Volume",
+                topics.state("chat_mix"),
+                json!({ "unit_of_measurement": "%", "value_template": "{{ value_json.game if value else None }}" })
+            )
+        ),
+        (
+            "sensor",
+            "chat_volume",
+            entity(
+                "Chat Volume",
+                topics.state("chat_mix"),
+                json!({ "unit_of_measurement": "%", "value_template": "{{ value_json.chat if value else None }}" })
+            )
+        ),
+        (
+            "select",
+            "profile",
+            entity(
+                "Profile",
+                topics.state("profile"),
+                json!({ "command_topic": topics.profile_command(), "options": state.profiles })
+            )
+        )
+    ];
+    entities
+        .into_iter()
+        // Home Assistant rejects a select without options
+        .filter(|(component, ..)| *component != "select" || !state.profiles.is_empty())
+        .filter_map(|(component, name, payload)| {
+            topics
+                .discovery(component, name)
+                .map(|topic| (topic, payload))
+        })
+        .collect()
+}
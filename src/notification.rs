@@ -1,9 +1,25 @@
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use std::sync::Mutex;
 use std::time::Duration;
 
 use color_eyre::Result;
+#[cfg(target_os = "linux")]
+use once_cell::sync::Lazy;
+#[cfg(target_os = "linux")]
+use tokio::runtime::Runtime;
+#[cfg(target_os = "linux")]
+use zbus::dbus_proxy;
 
+use crate::config::NotificationUrgency;
+#[cfg(target_os = "linux")]
+use crate::AppEvent;
+use crate::EventSender;
+
+/// Toasts have no urgency on Windows, so it is ignored there
 #[cfg(target_os = "windows")]
-pub fn notify(msg_title: &str, msg_body: &str, duration: Duration) -> Result<()> {
+pub fn notify(msg_title: &str, msg_body: &str, duration: Duration, _urgency: NotificationUrgency) -> Result<()> {
     use std::thread;
 
     use windows::core::HSTRING;
@@ -34,11 +50,134 @@ pub fn notify(msg_title: &str, msg_body: &str, duration: Duration) -> Result<()>
 }
 
 #[cfg(not(target_os = "windows"))]
-pub fn notify(msg_title: &str, msg_body: &str, duration: Duration) -> Result<()> {
-    notify_rust::Notification::new()
+fn build(msg_title: &str, msg_body: &str, duration: Duration, urgency: NotificationUrgency) -> notify_rust::Notification {
+    let mut notification = notify_rust::Notification::new();
+    notification
         .summary(msg_title)
         .body(msg_body)
-        .timeout(duration)
-        .show()?;
+        .timeout(duration);
+    #[cfg(all(unix, not(target_os = "macos")))]
+    notification.urgency(match urgency {
+        NotificationUrgency::Low => notify_rust::Urgency::Low,
+        NotificationUrgency::Normal => notify_rust::Urgency::Normal,
+        NotificationUrgency::Critical => notify_rust::Urgency::Critical
+    });
+    #[cfg(target_os = "macos")]
+    let _ = urgency;
+    notification
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn notify(msg_title: &str, msg_body: &str, duration: Duration, urgency: NotificationUrgency) -> Result<()> {
+    build(msg_title, msg_body, duration, urgency).show()?;
     Ok(())
 }
+
+/// A button of a notification, a click arrives as [crate::AppEvent::Notification]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum NotificationAction {
+    OpenSettings,
+    SelectProfile(String),
+    InstallUdevRules
+}
+
+impl NotificationAction {
+    pub fn label(&self) -> String {
+        match self {
+            NotificationAction::OpenSettings => String::from("Open settings"),
+            NotificationAction::SelectProfile(profile) => format!("Switch to {} profile", profile),
+            NotificationAction::InstallUdevRules => String::from("Install udev rules")
+        }
+    }
+}
+
+/// Like [notify], but with buttons. They are only shown on Linux, the other platforms show a plain notification.
+///
+/// The clicks are delivered by the listener of [listen_for_actions].
+#[cfg(target_os = "linux")]
+pub fn notify_with_actions(
+    msg_title: &str, msg_body: &str, duration: Duration, urgency: NotificationUrgency, actions: Vec<NotificationAction>, events: EventSender
+) -> Result<()> {
+    if actions.is_empty() {
+        return notify(msg_title, msg_body, duration, urgency);
+    }
+    let mut notification = build(msg_title, msg_body, duration, urgency);
+    for (i, action) in actions.iter().enumerate() {
+        notification.action(&i.to_string(), &action.label());
+    }
+    let handle = notification.show()?;
+    PENDING_ACTIONS
+        .lock()
+        .unwrap()
+        .insert(handle.id(), (actions, events));
+    Ok(())
+}
+
+/// The buttons of the shown notifications by their id
+#[cfg(target_os = "linux")]
+static PENDING_ACTIONS: Lazy<Mutex<HashMap<u32, (Vec<NotificationAction>, EventSender)>>> = Lazy::new(Default::default);
+
+#[cfg(target_os = "linux")]
+#[dbus_proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[dbus_proxy(signal)]
+    fn action_invoked(&self, id: u32, action_key: &str) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+/// Starts the single listener for the clicks on every notification of [notify_with_actions]
+#[cfg(target_os = "linux")]
+pub fn listen_for_actions(runtime: &Runtime) {
+    runtime.spawn(async {
+        listen()
+            .await
+            .unwrap_or_else(|err| tracing::warn!("Could not listen for notification actions: {:?}", err))
+    });
+}
+
+#[cfg(target_os = "linux")]
+async fn listen() -> zbus::Result<()> {
+    use futures_lite::StreamExt;
+
+    let connection = zbus::Connection::session().await?;
+    let proxy = NotificationsProxy::new(&connection).await?;
+    let mut invoked = proxy.receive_action_invoked().await?;
+    let mut closed = proxy.receive_notification_closed().await?;
+    loop {
+        tokio::select! {
+            // A click also closes the notification, so the click has to be handled first
+            biased;
+            Some(signal) = invoked.next() => {
+                let args = signal.args()?;
+                let pending = PENDING_ACTIONS.lock().unwrap().remove(args.id());
+                let Some((actions, events)) = pending else {
+                    continue;
+                };
+                if let Some(action) = args.action_key().parse::<usize>().ok().and_then(|i| actions.get(i)) {
+                    tracing::debug!("Clicked {:?}", action);
+                    events
+                        .send_event(AppEvent::Notification(action.clone()))
+                        .unwrap_or_else(|_| tracing::warn!("Could not send the notification action"));
+                }
+            }
+            Some(signal) = closed.next() => {
+                PENDING_ACTIONS.lock().unwrap().remove(signal.args()?.id());
+            }
+            else => break
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn notify_with_actions(
+    msg_title: &str, msg_body: &str, duration: Duration, urgency: NotificationUrgency, _actions: Vec<NotificationAction>, _events: EventSender
+) -> Result<()> {
+    notify(msg_title, msg_body, duration, urgency)
+}
@@ -0,0 +1,168 @@
+use std::net::SocketAddr;
+
+use color_eyre::Result;
+use rosc::{OscMessage, OscPacket, OscType};
+use tokio::net::UdpSocket;
+use tokio::runtime::Runtime;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::cli::Status;
+use crate::config::OscConfig;
+use crate::ipc::{dispatch, IpcRequest, IpcResponse};
+use crate::EventSender;
+
+/// Receives `/headset/...` messages over UDP and mirrors the state to `send_to`
+pub struct OscServer {
+    status: watch::Sender<Option<Status>>,
+    task: JoinHandle<()>
+}
+
+impl OscServer {
+    pub fn start(runtime: &Runtime, config: &OscConfig, events: EventSender) -> Result<Self> {
+        let socket = runtime.block_on(UdpSocket::bind(&config.listen))?;
+        let target = match &config.send_to {
+            Some(target) => runtime.block_on(tokio::net::lookup_host(target))?.next(),
+            None => None
+        };
+        let (status, receiver) = watch::channel(None);
+        let task = runtime.spawn(run(socket, target, receiver, events));
+        tracing::info!("Listening for osc messages on {}", config.listen);
+        Ok(Self { status, task })
+    }
+
+    pub fn update(&self, status: Option<Status>) {
+        self.status.send_replace(status);
+    }
+}
+
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run(socket: UdpSocket, target: Option<SocketAddr>, mut status: watch::Receiver<Option<Status>>, events: EventSender) {
+    let mut buffer = vec![0u8; rosc::decoder::MTU];
+    let mut sent: Option<Vec<OscMessage>> = None;
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buffer) => match received {
+                Ok((size, sender)) => match rosc::decoder::decode_udp(&buffer[..size]) {
+                    Ok((_, packet)) => handle_packet(packet, &events),
+                    Err(err) => tracing::debug!("Received an invalid osc packet from {}: {:?}", sender, err)
+                },
+                Err(err) => tracing::warn!("Could not receive osc packet: {}", err)
+            },
+            changed = status.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let messages = messages(status.borrow_and_update().as_ref());
+                if let Some(target) = target {
+                    for (i, message) in messages.iter().enumerate() {
+                        if sent.as_ref().map_or(true, |sent| sent[i] != *message) {
+                            send(&socket, target, message.clone()).await;
+                        }
+                    }
+                }
+                sent = Some(messages);
+            }
+        }
+    }
+}
+
+async fn send(socket: &UdpSocket, target: SocketAddr, message: OscMessage) {
+    match rosc::encoder::encode(&OscPacket::Message(message)) {
+        Ok(packet) => {
+            if let Err(err) = socket.send_to(&packet, target).await {
+                tracing::warn!("Could not send osc message to {}: {}", target, err);
+            }
+        }
+        Err(err) => tracing::warn!("Could not encode osc message: {:?}", err)
+    }
+}
+
+/// The state as seen by a control surface. Unknown values are sent as -1.
+fn messages(status: Option<&Status>) -> Vec<OscMessage> {
+    let message = |addr: &str, arg: OscType| OscMessage {
+        addr: addr.to_string(),
+        args: vec![arg]
+    };
+    let flag = |value: bool| OscType::Int(value as i32);
+    let chat_mix = status.and_then(|s| s.chat_mix);
+    vec![
+        message("/headset/connected", flag(status.map_or(false, |s| s.connected))),
+        message("/headset/battery", OscType::Int(status.and_then(|s| s.battery).map_or(-1, i32::from))),
+        message("/headset/charging", flag(status.map_or(false, |s| s.charging))),
+        message("/headset/chatmix/game", OscType::Int(chat_mix.map_or(-1, |mix| mix.game as i32))),
+        message("/headset/chatmix/chat", OscType::Int(chat_mix.map_or(-1, |mix| mix.chat as i32))),
+        message("/headset/profile", OscType::String(status.map_or_else(String::new, |s| s.profile.clone())))
+    ]
+}
+
+fn handle_packet(packet: OscPacket, events: &EventSender) {
+    match packet {
+        OscPacket::Message(message) => match parse_request(&message) {
+            Ok(request) => {
+                let events = events.clone();
+                tokio::spawn(async move {
+                    if let IpcResponse::Error { message, .. } = dispatch(request, events).await {
+                        tracing::warn!("Could not handle osc message: {}", message);
+                    }
+                });
+            }
+            Err(err) => tracing::warn!("Ignoring osc message {}: {}", message.addr, err)
+        },
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                handle_packet(packet, events);
+            }
+        }
+    }
+}
+
+/// Maps `/headset/profile`, `/headset/eq/<band>` (starting at 1) and `/headset/<setting>` to requests
+fn parse_request(message: &OscMessage) -> Result<IpcRequest, String> {
+    let path = message
+        .addr
+        .strip_prefix("/headset/")
+        .ok_or_else(|| String::from("Expected an address starting with /headset/"))?;
+    let value = message
+        .args
+        .first()
+        .and_then(argument)
+        .ok_or_else(|| String::from("Expected a number, string or boolean argument"))?;
+    match path.split('/').collect::<Vec<_>>().as_slice() {
+        ["profile"] => Ok(IpcRequest::SelectProfile(value)),
+        ["eq", band] => {
+            let band = band
+                .parse::<usize>()
+                .ok()
+                .filter(|band| *band > 0)
+                .ok_or_else(|| format!("Invalid band {}", band))?;
+            let level = value
+                .parse::<u8>()
+                .map_err(|_| format!("Invalid level {}", value))?;
+            Ok(IpcRequest::SetEqualizerBand { band: band - 1, level })
+        }
+        [setting] => Ok(IpcRequest::SetSetting {
+            setting: setting.to_string(),
+            value
+        }),
+        _ => Err(String::from("Unknown address"))
+    }
+}
+
+/// Converts the argument to the text form the `set` command accepts
+fn argument(arg: &OscType) -> Option<String> {
+    match arg {
+        OscType::Int(value) => Some(value.to_string()),
+        OscType::Long(value) => Some(value.to_string()),
+        OscType::Float(value) => Some((value.round() as i64).to_string()),
+        OscType::Double(value) => Some((value.round() as i64).to_string()),
+        OscType::String(value) => Some(value.clone()),
+        OscType::Bool(value) => Some(String::from(if *value { "on" } else { "off" })),
+        _ => None
+    }
+}
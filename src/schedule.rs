@@ -0,0 +1,276 @@
+use std::iter::successors;
+use std::time::{Duration, Instant};
+
+use chrono::{Datelike, Days, Local, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::config::{Day, Schedule};
+use crate::ipc::IpcRequest;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How far back the schedules are applied after a start or a device switch
+const CATCH_UP: Days = Days::new(7);
+
+/// The source of the local time, replaceable to test the scheduler
+pub trait Clock {
+    /// The wall clock time the schedules are compared against
+    fn now(&self) -> NaiveDateTime;
+    /// The monotonic time that spaces out the polls
+    fn instant(&self) -> Instant;
+}
+
+pub struct LocalClock;
+
+impl Clock for LocalClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Runs the [Schedule]s of the current headset.
+///
+/// All times are compared as wall clock times and the checked range never moves backwards. A schedule that falls into the
+/// hour skipped by a DST change runs right after the skip, and one in the repeated hour only runs once.
+pub struct Scheduler<C = LocalClock> {
+    clock: C,
+    next_poll: Instant,
+    /// The device and the wall clock time of the last poll
+    last: Option<(String, NaiveDateTime)>
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::with_clock(LocalClock)
+    }
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            next_poll: clock.instant(),
+            clock,
+            last: None
+        }
+    }
+
+    pub fn next_poll(&self) -> Option<Instant> {
+        self.last.as_ref().map(|_| self.next_poll)
+    }
+
+    /// Returns the requests of every schedule that became due since the last poll in the order they were due.
+    ///
+    /// The first poll for a device catches up on the last occurrence of every schedule, so the state survives restarts.
+    pub fn poll(&mut self, device: &str, schedules: &[Schedule]) -> Vec<IpcRequest> {
+        if self.next_poll > self.clock.instant() && self.last.as_ref().map_or(false, |(name, _)| name == device) {
+            return Vec::new();
+        }
+        self.next_poll = self.clock.instant() + POLL_INTERVAL;
+        let now = self.clock.now();
+        let from = match &self.last {
+            Some((name, last)) if name == device => *last,
+            _ => now.checked_sub_days(CATCH_UP).unwrap_or(now)
+        };
+        if now <= from {
+            // The clock went back, e.g. at the end of DST
+            return Vec::new();
+        }
+        self.last = Some((device.to_string(), now));
+        let mut due = schedules
+            .iter()
+            .filter(|schedule| schedule.enabled)
+            .filter_map(|schedule| last_occurrence(schedule, from, now).map(|time| (time, schedule)))
+            .collect::<Vec<_>>();
+        due.sort_by_key(|(time, _)| *time);
+        due.into_iter()
+            .flat_map(|(_, schedule)| {
+                tracing::info!("Running schedule \"{}\"", schedule.name);
+                requests(schedule)
+            })
+            .collect()
+    }
+}
+
+/// The latest time in `(from, to]` at which the schedule runs
+fn last_occurrence(schedule: &Schedule, from: NaiveDateTime, to: NaiveDateTime) -> Option<NaiveDateTime> {
+    let time = NaiveTime::from_hms_opt(schedule.time.hour as u32, schedule.time.minute as u32, 0)?;
+    successors(Some(to.date()), |date| date.pred_opt())
+        .take_while(|date| *date >= from.date())
+        .filter(|date| schedule.days.is_empty() || schedule.days.contains(&day(date.weekday())))
+        .map(|date| date.and_time(time))
+        .find(|occurrence| *occurrence > from && *occurrence <= to)
+}
+
+fn requests(schedule: &Schedule) -> Vec<IpcRequest> {
+    let profile = schedule
+        .profile
+        .iter()
+        .map(|profile| IpcRequest::SelectProfile(profile.clone()));
+    // The settings go second so they apply to the new profile
+    let settings = schedule
+        .settings
+        .iter()
+        .map(|setting| IpcRequest::SetSettingById {
+            id: setting.setting,
+            value: setting.value.clone()
+        });
+    profile.chain(settings).collect()
+}
+
+fn day(weekday: Weekday) -> Day {
+    Day::DAYS[weekday.num_days_from_monday() as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::config::TimeOfDay;
+
+    #[derive(Clone)]
+    struct FakeClock(Rc<Cell<(NaiveDateTime, Instant)>>);
+
+    impl FakeClock {
+        fn new(now: &str) -> Self {
+            Self(Rc::new(Cell::new((at(now), Instant::now()))))
+        }
+
+        /// Lets `elapsed` pass and sets the wall clock to `wall`, which differ around DST changes
+        fn set(&self, wall: &str, elapsed: Duration) {
+            let (_, instant) = self.0.get();
+            self.0.set((at(wall), instant + elapsed));
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> NaiveDateTime {
+            self.0.get().0
+        }
+
+        fn instant(&self) -> Instant {
+            self.0.get().1
+        }
+    }
+
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn schedule(name: &str, days: Vec<Day>, hour: u8, minute: u8) -> Schedule {
+        Schedule {
+            name: name.to_string(),
+            days,
+            time: TimeOfDay { hour, minute },
+            profile: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn profiles(requests: Vec<IpcRequest>) -> Vec<String> {
+        requests
+            .into_iter()
+            .filter_map(|request| match request {
+                IpcRequest::SelectProfile(profile) => Some(profile),
+                _ => None
+            })
+            .collect()
+    }
+
+    /// A scheduler that already caught up on the schedules
+    fn started(clock: &FakeClock, schedules: &[Schedule]) -> Scheduler<FakeClock> {
+        let mut scheduler = Scheduler::with_clock(clock.clone());
+        scheduler.poll("Headset", schedules);
+        scheduler
+    }
+
+    #[test]
+    fn first_poll_catches_up_in_order() {
+        // A Friday
+        let clock = FakeClock::new("2026-10-16 12:00:00");
+        let disabled = Schedule {
+            enabled: false,
+            ..schedule("Disabled", vec![], 11, 30)
+        };
+        let schedules = [
+            schedule("Recent", vec![], 11, 0),
+            schedule("Old", vec![Day::Monday], 8, 0),
+            schedule("Yesterday", vec![], 13, 0),
+            disabled
+        ];
+        let mut scheduler = Scheduler::with_clock(clock.clone());
+        assert_eq!(profiles(scheduler.poll("Headset", &schedules)), ["Old", "Yesterday", "Recent"]);
+
+        // A different device catches up again
+        clock.set("2026-10-16 12:00:01", Duration::from_secs(1));
+        assert_eq!(profiles(scheduler.poll("Other", &schedules)).len(), 3);
+    }
+
+    #[test]
+    fn polls_are_spaced_out() {
+        let clock = FakeClock::new("2026-10-16 11:59:55");
+        let schedules = [schedule("Noon", vec![], 12, 0)];
+        let mut scheduler = started(&clock, &schedules);
+
+        clock.set("2026-10-16 12:00:05", Duration::from_secs(10));
+        assert!(scheduler.poll("Headset", &schedules).is_empty());
+        assert_eq!(scheduler.next_poll(), Some(clock.instant() + Duration::from_secs(20)));
+
+        clock.set("2026-10-16 12:00:30", Duration::from_secs(25));
+        assert_eq!(profiles(scheduler.poll("Headset", &schedules)), ["Noon"]);
+    }
+
+    #[test]
+    fn day_filter() {
+        let clock = FakeClock::new("2026-10-16 09:59:00");
+        let schedules = [schedule("Weekend", vec![Day::Saturday], 10, 0)];
+        let mut scheduler = started(&clock, &schedules);
+
+        // Friday
+        clock.set("2026-10-16 10:00:30", Duration::from_secs(90));
+        assert!(scheduler.poll("Headset", &schedules).is_empty());
+
+        // Saturday
+        clock.set("2026-10-17 10:00:30", Duration::from_secs(24 * 60 * 60));
+        assert_eq!(profiles(scheduler.poll("Headset", &schedules)), ["Weekend"]);
+
+        clock.set("2026-10-18 10:00:30", Duration::from_secs(24 * 60 * 60));
+        assert!(scheduler.poll("Headset", &schedules).is_empty());
+    }
+
+    #[test]
+    fn spring_forward_runs_skipped_schedule_once() {
+        let clock = FakeClock::new("2026-03-29 01:59:50");
+        let schedules = [schedule("Skipped", vec![], 2, 30)];
+        let mut scheduler = started(&clock, &schedules);
+
+        // The clock jumps from 02:00 to 03:00
+        clock.set("2026-03-29 03:00:20", Duration::from_secs(30));
+        assert_eq!(profiles(scheduler.poll("Headset", &schedules)), ["Skipped"]);
+
+        clock.set("2026-03-29 03:00:50", Duration::from_secs(30));
+        assert!(scheduler.poll("Headset", &schedules).is_empty());
+    }
+
+    #[test]
+    fn fall_back_runs_repeated_schedule_once() {
+        let clock = FakeClock::new("2026-10-25 02:29:50");
+        let schedules = [schedule("Repeated", vec![], 2, 30)];
+        let mut scheduler = started(&clock, &schedules);
+
+        clock.set("2026-10-25 02:30:20", Duration::from_secs(30));
+        assert_eq!(profiles(scheduler.poll("Headset", &schedules)), ["Repeated"]);
+
+        // The clock goes back from 03:00 to 02:00
+        for wall in ["2026-10-25 02:59:50", "2026-10-25 02:00:20", "2026-10-25 02:30:20", "2026-10-25 03:00:20"] {
+            clock.set(wall, Duration::from_secs(30 * 60));
+            assert!(scheduler.poll("Headset", &schedules).is_empty(), "ran again at {}", wall);
+        }
+
+        clock.set("2026-10-26 02:30:20", Duration::from_secs(24 * 60 * 60));
+        assert_eq!(profiles(scheduler.poll("Headset", &schedules)), ["Repeated"]);
+    }
+}
@@ -0,0 +1,328 @@
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{Datelike, Local, Timelike};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+use crate::cli::{Status, StatusChange};
+use crate::config::Config;
+use crate::ipc::IpcRequest;
+
+/// How long a single call into a script may take before it is aborted
+const TIME_BUDGET: Duration = Duration::from_millis(50);
+const TICK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub enum ScriptCommand {
+    Request(IpcRequest),
+    Notify(String)
+}
+
+/// Runs the automation rules of the user script.
+///
+/// The script can define `on_change(event, status)`, which is called for every [StatusChange], and `on_tick(status)`,
+/// which is called once a minute. Both can call `select_profile`, `set_setting` and `notify`.
+///
+/// Profile changes caused by the script itself are not passed back to `on_change`, so two rules that switch back and
+/// forth between profiles can not keep each other running.
+pub struct Scripts {
+    engine: Engine,
+    path: PathBuf,
+    script: Option<(SystemTime, AST)>,
+    commands: Rc<RefCell<Vec<ScriptCommand>>>,
+    deadline: Rc<Cell<Instant>>,
+    next_tick: Instant,
+    /// Set while a profile change caused by the script did not show up in [Scripts::on_changes] yet
+    selected_profile: bool
+}
+
+impl Scripts {
+    pub fn new() -> Self {
+        Self::with_path(Config::script_path().to_path_buf())
+    }
+
+    fn with_path(path: PathBuf) -> Self {
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        let deadline = Rc::new(Cell::new(Instant::now()));
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(1_000_000)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(10_000)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000)
+            .disable_symbol("eval");
+        engine.on_print(|text| tracing::info!("Script: {}", text));
+        engine.on_debug(|text, _, _| tracing::debug!("Script: {}", text));
+        {
+            let deadline = deadline.clone();
+            engine.on_progress(move |_| match Instant::now() > deadline.get() {
+                true => Some(Dynamic::from("The script exceeded its time budget")),
+                false => None
+            });
+        }
+        {
+            let commands = commands.clone();
+            engine.register_fn("select_profile", move |profile: &str| {
+                commands
+                    .borrow_mut()
+                    .push(ScriptCommand::Request(IpcRequest::SelectProfile(profile.to_string())))
+            });
+        }
+        {
+            let commands = commands.clone();
+            engine.register_fn("set_setting", move |setting: &str, value: Dynamic| {
+                commands
+                    .borrow_mut()
+                    .push(ScriptCommand::Request(IpcRequest::SetSetting {
+                        setting: setting.to_string(),
+                        value: value.to_string()
+                    }))
+            });
+        }
+        {
+            let commands = commands.clone();
+            engine.register_fn("notify", move |text: &str| {
+                commands
+                    .borrow_mut()
+                    .push(ScriptCommand::Notify(text.to_string()))
+            });
+        }
+        engine.register_fn("now", || {
+            let now = Local::now();
+            let mut map = Map::new();
+            map.insert("hour".into(), Dynamic::from(now.hour() as i64));
+            map.insert("minute".into(), Dynamic::from(now.minute() as i64));
+            map.insert("weekday".into(), Dynamic::from(now.weekday().num_days_from_monday() as i64));
+            map
+        });
+        Self {
+            engine,
+            path,
+            script: None,
+            commands,
+            deadline,
+            next_tick: Instant::now() + TICK_INTERVAL,
+            selected_profile: false
+        }
+    }
+
+    /// The commands the script issued since the last call
+    pub fn take_commands(&mut self) -> Vec<ScriptCommand> {
+        self.commands.take()
+    }
+
+    /// Reports whether a profile selected by the script differs from the active one, as only then a
+    /// [StatusChange::ProfileChanged] follows that must not be passed back to the script
+    pub fn profile_selected(&mut self, changed: bool) {
+        self.selected_profile = changed;
+    }
+
+    pub fn next_tick(&self) -> Option<Instant> {
+        self.script.as_ref().map(|_| self.next_tick)
+    }
+
+    pub fn on_changes(&mut self, changes: &[StatusChange], status: &Status) {
+        self.reload();
+        let own_profile_change = std::mem::take(&mut self.selected_profile);
+        for change in changes {
+            if own_profile_change && matches!(change, StatusChange::ProfileChanged { .. }) {
+                tracing::debug!("Not passing the profile change of the script back to it");
+                continue;
+            }
+            self.call("on_change", vec![Dynamic::from_map(event(change)), Dynamic::from_map(status_map(status))]);
+        }
+    }
+
+    /// Calls `on_tick` if a minute passed since the last call
+    pub fn tick(&mut self, status: Option<&Status>) {
+        if self.next_tick > Instant::now() {
+            return;
+        }
+        self.next_tick = Instant::now() + TICK_INTERVAL;
+        self.reload();
+        if let Some(status) = status {
+            self.call("on_tick", vec![Dynamic::from_map(status_map(status))]);
+        }
+    }
+
+    /// Recompiles the script whenever the file changed on disk
+    fn reload(&mut self) {
+        let path = self.path.as_path();
+        let modified = match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(_) => {
+                if self.script.take().is_some() {
+                    tracing::info!("Unloaded {}", path.display());
+                }
+                return;
+            }
+        };
+        if self.script.as_ref().map(|(time, _)| *time) == Some(modified) {
+            return;
+        }
+        self.script = match self.compile(path) {
+            Ok(ast) => {
+                tracing::info!("Loaded {}", path.display());
+                Some((modified, ast))
+            }
+            Err(err) => {
+                tracing::warn!("Could not load {}: {}", path.display(), err);
+                // Remember the broken version so the error is only reported once
+                Some((modified, AST::empty()))
+            }
+        };
+    }
+
+    fn compile(&self, path: &Path) -> Result<AST, String> {
+        let source = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        self.engine
+            .compile(source)
+            .map_err(|err| err.to_string())
+    }
+
+    fn call(&self, name: &str, args: Vec<Dynamic>) {
+        let Some((_, ast)) = &self.script else {
+            return;
+        };
+        let defined = ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == args.len());
+        if !defined {
+            return;
+        }
+        self.deadline.set(Instant::now() + TIME_BUDGET);
+        if let Err(err) = self
+            .engine
+            .call_fn::<Dynamic>(&mut Scope::new(), ast, name, args)
+        {
+            tracing::warn!("The script failed in {}: {}", name, err);
+        }
+    }
+}
+
+fn event(change: &StatusChange) -> Map {
+    let mut map = Map::new();
+    let kind = match change {
+        StatusChange::Connected => "connected",
+        StatusChange::Disconnected => "disconnected",
+        StatusChange::BatteryChanged { previous, current } => {
+            map.insert("previous".into(), previous.map_or(Dynamic::UNIT, |level| Dynamic::from(level as i64)));
+            map.insert("current".into(), Dynamic::from(*current as i64));
+            "battery"
+        }
+        StatusChange::ChargingStarted => "charging_started",
+        StatusChange::ChargingFinished => "charging_finished",
+        StatusChange::ProfileChanged { previous } => {
+            map.insert("previous".into(), Dynamic::from(previous.clone()));
+            "profile"
+        }
+        StatusChange::ChatMixChanged => "chat_mix"
+    };
+    map.insert("kind".into(), kind.into());
+    map
+}
+
+fn status_map(status: &Status) -> Map {
+    let mut map = Map::new();
+    map.insert("device".into(), Dynamic::from(status.device.clone()));
+    map.insert("connected".into(), Dynamic::from(status.connected));
+    map.insert("battery".into(), status.battery.map_or(Dynamic::UNIT, |level| Dynamic::from(level as i64)));
+    map.insert("charging".into(), Dynamic::from(status.charging));
+    map.insert("game".into(), status.chat_mix.map_or(Dynamic::UNIT, |mix| Dynamic::from(mix.game as i64)));
+    map.insert("chat".into(), status.chat_mix.map_or(Dynamic::UNIT, |mix| Dynamic::from(mix.chat as i64)));
+    map.insert("profile".into(), Dynamic::from(status.profile.clone()));
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(profile: &str) -> Status {
+        Status {
+            device: String::from("Dummy"),
+            connected: true,
+            battery: Some(50),
+            charging: false,
+            chat_mix: None,
+            profile: profile.to_string()
+        }
+    }
+
+    fn scripts(name: &str, source: &str) -> Scripts {
+        let path = std::env::temp_dir().join(format!("headset-controller-test-{}-{}.rhai", std::process::id(), name));
+        std::fs::write(&path, source).unwrap();
+        Scripts::with_path(path)
+    }
+
+    fn selected_profiles(commands: Vec<ScriptCommand>) -> Vec<String> {
+        commands
+            .into_iter()
+            .filter_map(|command| match command {
+                ScriptCommand::Request(IpcRequest::SelectProfile(profile)) => Some(profile),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ping_pong_script_stops() {
+        let mut scripts = scripts(
+            "ping_pong",
+            r#"fn on_change(e, s) { if e.kind == "profile" { select_profile(e.previous) } }"#
+        );
+        let user_change = [StatusChange::ProfileChanged { previous: String::from("Music") }];
+        scripts.on_changes(&user_change, &status("Gaming"));
+        assert_eq!(selected_profiles(scripts.take_commands()), ["Music"]);
+        scripts.profile_selected(true);
+
+        // The switch back done by the script must not trigger it again
+        let script_change = [StatusChange::ProfileChanged { previous: String::from("Gaming") }];
+        scripts.on_changes(&script_change, &status("Music"));
+        assert!(scripts.take_commands().is_empty());
+
+        // Later changes by the user reach the script again
+        scripts.on_changes(&script_change, &status("Music"));
+        assert_eq!(selected_profiles(scripts.take_commands()), ["Gaming"]);
+        std::fs::remove_file(&scripts.path).unwrap();
+    }
+
+    #[test]
+    fn other_changes_still_reach_the_script() {
+        let mut scripts = scripts(
+            "battery",
+            r#"fn on_change(e, s) { if e.kind == "profile" { select_profile("Music") } if e.kind == "battery" { notify("low") } }"#
+        );
+        scripts.on_changes(&[StatusChange::ProfileChanged { previous: String::from("Music") }], &status("Gaming"));
+        scripts.take_commands();
+        scripts.profile_selected(true);
+        let changes = [
+            StatusChange::ProfileChanged { previous: String::from("Gaming") },
+            StatusChange::BatteryChanged { previous: Some(50), current: 10 }
+        ];
+        scripts.on_changes(&changes, &status("Music"));
+        assert!(matches!(scripts.take_commands().as_slice(), [ScriptCommand::Notify(_)]));
+        std::fs::remove_file(&scripts.path).unwrap();
+    }
+
+    #[test]
+    fn selecting_the_active_profile_does_not_swallow_the_next_change() {
+        let mut scripts = scripts(
+            "active",
+            r#"fn on_change(e, s) { if e.kind == "battery" { select_profile(s.profile) } if e.kind == "profile" { notify("changed") } }"#
+        );
+        scripts.on_changes(&[StatusChange::BatteryChanged { previous: Some(50), current: 40 }], &status("Gaming"));
+        assert_eq!(selected_profiles(scripts.take_commands()), ["Gaming"]);
+        scripts.profile_selected(false);
+
+        // The profile did not change, so the next profile change comes from the user
+        let user_change = [StatusChange::ProfileChanged { previous: String::from("Gaming") }];
+        scripts.on_changes(&user_change, &status("Music"));
+        assert!(matches!(scripts.take_commands().as_slice(), [ScriptCommand::Notify(_)]));
+        std::fs::remove_file(&scripts.path).unwrap();
+    }
+}
@@ -1,28 +1,85 @@
+use std::panic::AssertUnwindSafe;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
 use tao::event_loop::EventLoopWindowTarget;
 use tao::menu::{ContextMenu, CustomMenuItem, MenuId, MenuItem, MenuItemAttributes};
 use tao::system_tray::{SystemTray, SystemTrayBuilder};
 
+use crate::config::NoiseCancellingMode;
 use crate::ui::WINDOW_ICON;
 
+/// The well-known name of the StatusNotifier watcher that tray hosts register with
+#[cfg(target_os = "linux")]
+const STATUS_NOTIFIER_WATCHER: &str = "org.kde.StatusNotifierWatcher";
+
+/// Checks the session bus for a StatusNotifier watcher, without one the tray icon would never show up
+#[cfg(target_os = "linux")]
+pub async fn host_available() -> zbus::Result<bool> {
+    let connection = zbus::Connection::session().await?;
+    let proxy = zbus::fdo::DBusProxy::new(&connection).await?;
+    Ok(proxy
+        .name_has_owner(zbus::names::BusName::try_from(STATUS_NOTIFIER_WATCHER)?)
+        .await?)
+}
+
+/// Waits until a StatusNotifier watcher appears on the session bus
+#[cfg(target_os = "linux")]
+pub async fn wait_for_host() -> zbus::Result<()> {
+    use futures_lite::StreamExt;
+
+    let connection = zbus::Connection::session().await?;
+    let proxy = zbus::fdo::DBusProxy::new(&connection).await?;
+    let mut changes = proxy
+        .receive_name_owner_changed_with_args(&[(0, STATUS_NOTIFIER_WATCHER)])
+        .await?;
+    // Subscribing first ensures that a watcher appearing in between is not missed
+    if proxy
+        .name_has_owner(zbus::names::BusName::try_from(STATUS_NOTIFIER_WATCHER)?)
+        .await?
+    {
+        return Ok(());
+    }
+    while let Some(change) = changes.next().await {
+        if change.args()?.new_owner().is_some() {
+            return Ok(());
+        }
+    }
+    Err(zbus::Error::Failure(String::from("The session bus closed the signal stream")))
+}
+
+/// The entries of the tray menu, the window offers the same while there is no tray
+#[derive(Debug, Default)]
+pub struct TrayMenuEntries {
+    pub profiles: Vec<String>,
+    pub selected: usize,
+    pub noise_cancelling: Option<NoiseCancellingMode>
+}
+
 pub struct AppTray {
     tray: SystemTray,
     menu: TrayMenu
 }
 
 impl AppTray {
-    pub fn new<T>(event_loop: &EventLoopWindowTarget<T>) -> Self {
-        let (m, menu) = TrayMenu::new(0, |_| ("", false));
-        let tray = SystemTrayBuilder::new(WINDOW_ICON.clone(), Some(m))
-            .build(event_loop)
-            .expect("Could not build tray icon");
-        Self { tray, menu }
+    /// Fails if the desktop has no tray, use [host_available] to check for a StatusNotifier host on Linux first
+    pub fn new<T>(event_loop: &EventLoopWindowTarget<T>) -> Result<Self> {
+        let (m, menu) = TrayMenu::new(0, |_| ("", false), None);
+        let builder = SystemTrayBuilder::new(WINDOW_ICON.clone(), Some(m));
+        // libappindicator-sys panics instead of returning an error if the library is not installed
+        let tray = std::panic::catch_unwind(AssertUnwindSafe(|| builder.build(event_loop)))
+            .map_err(|_| eyre!("Could not load libappindicator"))??;
+        Ok(Self { tray, menu })
     }
 
-    pub fn build_menu<'a, F>(&mut self, profile_count: usize, func: F)
-    where
-        F: Fn(usize) -> (&'a str, bool)
-    {
-        self.menu.update(&mut self.tray, profile_count, func)
+    pub fn build_menu(&mut self, entries: &TrayMenuEntries) {
+        let profiles = &entries.profiles;
+        self.menu.update(
+            &mut self.tray,
+            profiles.len(),
+            |id| (profiles[id].as_str(), id == entries.selected),
+            entries.noise_cancelling
+        )
     }
 
     pub fn set_tooltip(&mut self, tooltip: &str) {
@@ -36,6 +93,7 @@ impl AppTray {
 
 struct TrayMenu {
     profile_buttons: Vec<CustomMenuItem>,
+    noise_cancelling_buttons: Vec<CustomMenuItem>,
     quit_button: CustomMenuItem,
     open_button: CustomMenuItem
 }
@@ -46,7 +104,7 @@ fn next(id: &mut MenuId) -> MenuId {
 }
 
 impl TrayMenu {
-    pub fn new<'a, F>(profile_count: usize, func: F) -> (ContextMenu, Self)
+    pub fn new<'a, F>(profile_count: usize, func: F, noise_cancelling: Option<NoiseCancellingMode>) -> (ContextMenu, Self)
     where
         F: Fn(usize) -> (&'a str, bool)
     {
@@ -62,6 +120,20 @@ impl TrayMenu {
             profile_buttons.push(profiles.add_item(item));
         }
         menu.add_submenu("Profiles", profile_count > 0, profiles);
+        let mut noise_cancelling_buttons = Vec::new();
+        if let Some(current) = noise_cancelling {
+            let mut modes = ContextMenu::new();
+            for (mode, name) in NoiseCancellingMode::MODES
+                .into_iter()
+                .zip(NoiseCancellingMode::NAMES)
+            {
+                let item = MenuItemAttributes::new(name)
+                    .with_id(next(&mut id))
+                    .with_selected(mode == current);
+                noise_cancelling_buttons.push(modes.add_item(item));
+            }
+            menu.add_submenu("Noise Cancelling", true, modes);
+        }
         menu.add_native_item(MenuItem::Separator);
         let open_button = menu.add_item(MenuItemAttributes::new("Open").with_id(next(&mut id)));
         let quit_button = menu.add_item(MenuItemAttributes::new("Quit").with_id(next(&mut id)));
@@ -69,17 +141,18 @@ impl TrayMenu {
             menu,
             Self {
                 profile_buttons,
+                noise_cancelling_buttons,
                 quit_button,
                 open_button
             }
         )
     }
 
-    pub fn update<'a, F>(&mut self, tray: &mut SystemTray, profile_count: usize, func: F)
+    pub fn update<'a, F>(&mut self, tray: &mut SystemTray, profile_count: usize, func: F, noise_cancelling: Option<NoiseCancellingMode>)
     where
         F: Fn(usize) -> (&'a str, bool)
     {
-        if profile_count == self.profile_buttons.len() {
+        if profile_count == self.profile_buttons.len() && noise_cancelling.is_some() != self.noise_cancelling_buttons.is_empty() {
             tracing::trace!("Reusing existing menu");
             for (i, button) in self.profile_buttons.iter_mut().enumerate() {
                 let (name, selected) = func(i);
@@ -87,9 +160,15 @@ impl TrayMenu {
                 button.set_title(name);
                 button.set_selected(selected);
             }
+            for (mode, button) in NoiseCancellingMode::MODES
+                .into_iter()
+                .zip(self.noise_cancelling_buttons.iter_mut())
+            {
+                button.set_selected(noise_cancelling == Some(mode));
+            }
         } else {
             tracing::trace!("Creating new menu");
-            let (m, menu) = Self::new(profile_count, func);
+            let (m, menu) = Self::new(profile_count, func, noise_cancelling);
             tray.set_menu(&m);
             *self = menu;
         }
@@ -107,6 +186,14 @@ impl TrayMenu {
                 return Some(TrayEvent::Profile(i));
             }
         }
+        for (mode, button) in NoiseCancellingMode::MODES
+            .into_iter()
+            .zip(self.noise_cancelling_buttons.iter())
+        {
+            if button.clone().id() == id {
+                return Some(TrayEvent::NoiseCancelling(mode));
+            }
+        }
         None
     }
 }
@@ -115,5 +202,6 @@ impl TrayMenu {
 pub enum TrayEvent {
     Open,
     Quit,
-    Profile(usize)
+    Profile(usize),
+    NoiseCancelling(NoiseCancellingMode)
 }
@@ -0,0 +1,78 @@
+use egui::*;
+use tracing::instrument;
+
+use crate::config::{HeadsetConfig, ProcessRule};
+use crate::debouncer::{Action, Debouncer};
+
+fn profile_combo(ui: &mut Ui, id: impl std::hash::Hash, profiles: &[String], selected: &mut Option<String>, empty: &str) -> bool {
+    let mut changed = false;
+    ComboBox::from_id_source(id)
+        .selected_text(selected.as_deref().unwrap_or(empty))
+        .show_ui(ui, |ui| {
+            changed |= ui.selectable_value(selected, None, empty).changed();
+            for profile in profiles {
+                changed |= ui
+                    .selectable_value(selected, Some(profile.clone()), profile)
+                    .changed();
+            }
+        });
+    changed
+}
+
+#[instrument(skip_all)]
+pub fn auto_profile_ui(ui: &mut Ui, debouncer: &mut Debouncer, headset: &mut HeadsetConfig) {
+    let profiles = headset
+        .profiles
+        .iter()
+        .map(|profile| profile.name.clone())
+        .collect::<Vec<_>>();
+    let config = &mut headset.auto_profiles;
+    CollapsingHeader::new("Automatic Switching").show(ui, |ui| {
+        let mut changed = ui
+            .checkbox(&mut config.enabled, "Select profiles based on running applications")
+            .changed();
+        if config.enabled && config.pinned {
+            ui.horizontal(|ui| {
+                ui.label("Paused because the profile was changed manually");
+                if ui.button("Resume").clicked() {
+                    config.pinned = false;
+                    changed = true;
+                }
+            });
+        }
+        let mut removed = None;
+        Grid::new("auto_profile_rules")
+            .num_columns(3)
+            .show(ui, |ui| {
+                for (i, rule) in config.rules.iter_mut().enumerate() {
+                    changed |= ui
+                        .add(TextEdit::singleline(&mut rule.process).hint_text("Process"))
+                        .changed();
+                    let mut profile = Some(rule.profile.clone()).filter(|name| !name.is_empty());
+                    if profile_combo(ui, ("auto_profile_rule", i), &profiles, &mut profile, "Select Profile") {
+                        rule.profile = profile.unwrap_or_default();
+                        changed = true;
+                    }
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+        if let Some(i) = removed {
+            config.rules.remove(i);
+            changed = true;
+        }
+        if ui.button("Add Rule").clicked() {
+            config.rules.push(ProcessRule::default());
+            changed = true;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Otherwise:");
+            changed |= profile_combo(ui, "auto_profile_fallback", &profiles, &mut config.fallback, "Keep Current Profile");
+        });
+        if changed {
+            debouncer.submit(Action::SaveConfig);
+        }
+    });
+}
@@ -0,0 +1,273 @@
+use std::path::Path;
+
+use egui::plot::{Line, Plot};
+use egui::*;
+
+use crate::autoeq::{fit_equalizer, parse_parametric_eq, EqualizerFit, ParametricEq};
+use crate::config::{add_equalizer_preset, EqualizerConfig, EqualizerPreset, HeadsetConfig};
+use crate::debouncer::{Action, Debouncer};
+use crate::devices::{Equalizer, SettingId};
+use crate::dsp::{combined_response, format_frequency, log_frequencies, Biquad, MAX_FREQUENCY, MIN_FREQUENCY};
+
+/// Unknown presets fall back to `default`, the same way [HeadsetConfig::resolved_setting] does
+pub fn equalizer_ui(
+    ui: &mut Ui, debouncer: &mut Debouncer, auto_update: bool, conf: &mut EqualizerConfig, default: &EqualizerConfig,
+    user_presets: &mut Vec<EqualizerPreset>, equalizer: &dyn Equalizer
+) {
+    let action = Action::UpdateSetting(SettingId::Equalizer);
+    let range = (equalizer.base_level() - equalizer.variance())..=(equalizer.base_level() + equalizer.variance());
+    let presets = equalizer
+        .presets()
+        .iter()
+        .map(|(name, levels)| (name.to_string(), EqualizerConfig::Preset(name.to_string()), levels.to_vec()))
+        .chain(
+            user_presets
+                .iter()
+                .map(|preset| (preset.name.clone(), EqualizerConfig::UserPreset(preset.id), preset.levels.clone()))
+        )
+        .collect::<Vec<_>>();
+    let custom_index = presets.len();
+    if !matches!(conf, EqualizerConfig::Custom(_)) && !presets.iter().any(|(_, preset, _)| preset == conf) {
+        tracing::warn!("Unknown equalizer preset {:?}, using the default instead", conf);
+        *conf = default.clone();
+    }
+    let (mut current_index, mut levels) = match presets.iter().position(|(_, preset, _)| preset == conf) {
+        Some(i) => (i, presets[i].2.clone()),
+        None => match conf {
+            EqualizerConfig::Custom(levels) => (custom_index, levels.clone()),
+            _ => (custom_index, vec![equalizer.base_level(); equalizer.bands() as usize])
+        }
+    };
+    let mut save_preset = false;
+    let preset = ui
+        .horizontal(|ui| {
+            let preset = ComboBox::from_label("Equalizer").show_index(ui, &mut current_index, presets.len() + 1, |i| {
+                presets
+                    .get(i)
+                    .map_or_else(|| String::from("Custom"), |(name, _, _)| name.clone())
+            });
+            save_preset = ui
+                .button("Save as Preset")
+                .on_hover_text("Saves the current curve as a named preset that can be shared between profiles.")
+                .clicked();
+            preset
+        })
+        .inner;
+    let mut dirty = preset.changed();
+    ui.horizontal(|ui| {
+        ui.style_mut().spacing.slider_width = 150.0;
+        ui.style_mut().spacing.button_padding = vec2(5.0, 2.0);
+        ui.style_mut().spacing.interact_size = vec2(30.0, 20.0);
+        for (i, band) in levels.iter_mut().zip(equalizer.band_layout()) {
+            ui.vertical(|ui| {
+                let resp = Slider::new(i, range.clone())
+                    .vertical()
+                    .trailing_fill(true)
+                    .custom_formatter(|value, _| format!("{:+.1}", equalizer.gain(value as u8)))
+                    .custom_parser(|text| {
+                        text.trim_end_matches("dB")
+                            .trim()
+                            .parse::<f64>()
+                            .ok()
+                            .map(|gain| equalizer.base_level() as f64 + gain / equalizer.step_size())
+                    })
+                    .ui(ui)
+                    .on_hover_text(format!("{} Hz (Q {:.2})", format_frequency(band.frequency), band.q));
+                if resp.changed() {
+                    dirty |= true;
+                    current_index = custom_index;
+                }
+                if resp.drag_released() {
+                    debouncer.force(action);
+                }
+                ui.label(format_frequency(band.frequency));
+            });
+        }
+    });
+    let max_gain = equalizer.variance() as f64 * equalizer.step_size();
+    response_plot(ui, "equalizer_response", max_gain, vec![Line::new(device_curve(&levels, equalizer))]);
+    let import = import_ui(ui, equalizer);
+    let mut imported_levels = false;
+    if save_preset {
+        *conf = EqualizerConfig::UserPreset(add_equalizer_preset(user_presets, "Preset", levels.clone()));
+        debouncer.submit(Action::SaveConfig);
+    }
+    match import {
+        Some(ImportAction::Apply(imported)) => {
+            levels = imported;
+            current_index = custom_index;
+            dirty = true;
+            imported_levels = true;
+        }
+        Some(ImportAction::SavePreset(name, imported)) => {
+            *conf = EqualizerConfig::UserPreset(add_equalizer_preset(user_presets, &name, imported));
+            debouncer.submit(Action::SaveConfig);
+            if auto_update {
+                debouncer.submit(action);
+                debouncer.force(action);
+            }
+        }
+        None => {}
+    }
+    if dirty {
+        *conf = match presets.get(current_index) {
+            Some((_, preset, _)) => preset.clone(),
+            None => EqualizerConfig::Custom(levels)
+        };
+        debouncer.submit(Action::SaveConfig);
+        if auto_update {
+            debouncer.submit(action);
+        }
+    }
+    if preset.changed() || imported_levels {
+        debouncer.force(action);
+    }
+}
+
+fn device_curve(levels: &[u8], equalizer: &dyn Equalizer) -> Vec<[f64; 2]> {
+    let filters = levels
+        .iter()
+        .zip(equalizer.band_layout())
+        .map(|(level, band)| Biquad::peaking(band.frequency, band.q, equalizer.gain(*level)))
+        .collect::<Vec<_>>();
+    response_curve(&filters)
+}
+
+pub(super) fn response_curve(filters: &[Biquad]) -> Vec<[f64; 2]> {
+    log_frequencies(200)
+        .map(|frequency| [frequency.log10(), combined_response(filters, frequency)])
+        .collect()
+}
+
+pub(super) fn response_plot(ui: &mut Ui, id_source: &str, max_gain: f64, lines: Vec<Line>) {
+    Plot::new(id_source)
+        .height(120.0)
+        .width(ui.available_width().min(500.0))
+        .allow_zoom(false)
+        .allow_drag(false)
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        .allow_double_click_reset(false)
+        .include_x(MIN_FREQUENCY.log10())
+        .include_x(MAX_FREQUENCY.log10())
+        .include_y(-max_gain)
+        .include_y(max_gain)
+        .x_axis_formatter(|x, _| format_frequency(10f64.powf(x)))
+        .y_axis_formatter(|y, _| format!("{:+.0} dB", y))
+        .label_formatter(|_, point| format!("{} Hz\n{:+.1} dB", format_frequency(10f64.powf(point.x)), point.y))
+        .show(ui, |plot_ui| {
+            for line in lines {
+                plot_ui.line(line);
+            }
+        });
+}
+
+#[derive(Debug, Clone, Default)]
+struct ImportState {
+    path: String,
+    result: Option<Result<ImportedEqualizer, String>>
+}
+
+#[derive(Debug, Clone)]
+struct ImportedEqualizer {
+    name: String,
+    parametric_eq: ParametricEq,
+    fit: EqualizerFit
+}
+
+enum ImportAction {
+    Apply(Vec<u8>),
+    SavePreset(String, Vec<u8>)
+}
+
+fn load_parametric_eq(path: &str, equalizer: &dyn Equalizer) -> Result<ImportedEqualizer, String> {
+    let path = Path::new(path.trim().trim_matches('"'));
+    let text = std::fs::read_to_string(path).map_err(|err| format!("Can not read file: {}", err))?;
+    let parametric_eq = parse_parametric_eq(&text).map_err(|err| format!("Can not parse file: {}", err))?;
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default()
+        .trim_end_matches("ParametricEQ")
+        .trim()
+        .to_string();
+    let fit = fit_equalizer(&parametric_eq, equalizer);
+    Ok(ImportedEqualizer {
+        name,
+        parametric_eq,
+        fit
+    })
+}
+
+fn import_ui(ui: &mut Ui, equalizer: &dyn Equalizer) -> Option<ImportAction> {
+    let id = ui.make_persistent_id("parametric_eq_import");
+    let mut state = ui
+        .data_mut(|data| data.get_temp::<ImportState>(id))
+        .unwrap_or_default();
+    let mut action = None;
+    CollapsingHeader::new("Import Parametric EQ").show(ui, |ui| {
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut state.path).hint_text("Path to ParametricEQ.txt"))
+                .on_hover_text("Equalizer APO parametric filters, as published by AutoEQ.");
+            if ui.button("Load").clicked() {
+                state.result = Some(load_parametric_eq(&state.path, equalizer));
+            }
+        });
+        match &state.result {
+            Some(Ok(imported)) => {
+                ui.label(format!(
+                    "Fitting error: {:.2} dB RMS, {:.2} dB max",
+                    imported.fit.rms_error, imported.fit.max_error
+                ));
+                let target = log_frequencies(200)
+                    .map(|frequency| [frequency.log10(), imported.parametric_eq.response(frequency)])
+                    .collect::<Vec<_>>();
+                let target = Line::new(target).name("Target");
+                let fitted = Line::new(device_curve(&imported.fit.levels, equalizer)).name("Fitted");
+                let max_gain = equalizer.variance() as f64 * equalizer.step_size();
+                response_plot(ui, "parametric_eq_preview", max_gain, vec![target, fitted]);
+                ui.horizontal(|ui| {
+                    if ui.button("Apply as Custom").clicked() {
+                        action = Some(ImportAction::Apply(imported.fit.levels.clone()));
+                    }
+                    if ui.button("Save as Preset").clicked() {
+                        action = Some(ImportAction::SavePreset(imported.name.clone(), imported.fit.levels.clone()));
+                    }
+                });
+            }
+            Some(Err(err)) => {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+            None => {}
+        }
+    });
+    ui.data_mut(|data| data.insert_temp(id, state));
+    action
+}
+
+pub fn equalizer_presets_ui(ui: &mut Ui, debouncer: &mut Debouncer, headset: &mut HeadsetConfig) {
+    CollapsingHeader::new("Equalizer Presets").show(ui, |ui| {
+        if headset.equalizer_presets.is_empty() {
+            ui.label("Use \"Save as Preset\" in the profile section to create a preset.");
+        }
+        let mut deleted = None;
+        for (i, preset) in headset.equalizer_presets.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.text_edit_singleline(&mut preset.name).changed() {
+                    debouncer.submit(Action::SaveConfig);
+                }
+                if ui
+                    .button("Delete")
+                    .on_hover_text("Profiles using this preset keep its curve as a custom setting.")
+                    .clicked()
+                {
+                    deleted = Some(i);
+                }
+            });
+        }
+        if let Some(i) = deleted {
+            headset.remove_equalizer_preset(i);
+            debouncer.submit(Action::SaveConfig);
+        }
+    });
+}
@@ -2,53 +2,28 @@ use egui::*;
 use tracing::instrument;
 
 use crate::audio::{AudioDevice, AudioSystem};
-use crate::config::{CallAction, HeadsetConfig, OsAudio};
+use crate::config::{HeadsetConfig, OsAudio};
 use crate::debouncer::{Action, Debouncer};
-use crate::devices::Device;
-use crate::ui::ResponseExt;
+use crate::devices::{Device, SettingScope};
+use crate::ui::central_panel::equalizer::equalizer_presets_ui;
+use crate::ui::central_panel::settings::setting_ui;
 
 #[instrument(skip_all)]
 pub fn headset_section(
     ui: &mut Ui, debouncer: &mut Debouncer, auto_update: bool, headset: &mut HeadsetConfig, device: &dyn Device, audio_system: &mut AudioSystem
 ) {
-    if device.get_inactive_time().is_some() {
-        ui.horizontal(|ui| {
-            DragValue::new(&mut headset.inactive_time)
-                .clamp_range(5..=120)
-                .ui(ui)
-                .submit(debouncer, auto_update, Action::UpdateInactiveTime);
-            ui.label("Inactive Time");
-        });
+    for setting in device
+        .settings()
+        .iter()
+        .filter(|setting| setting.scope == SettingScope::Headset)
+    {
+        let value = headset.settings.value(setting, &headset.equalizer_presets);
+        setting_ui(ui, debouncer, auto_update, setting, value, &mut headset.equalizer_presets, device);
         ui.add_space(10.0);
     }
 
-    if let Some(mic_light) = device.get_mic_light() {
-        Slider::new(&mut headset.mic_light, 0..=(mic_light.levels() - 1))
-            .text("Microphone Light")
-            .ui(ui)
-            .submit(debouncer, auto_update, Action::UpdateMicrophoneLight);
-        ui.add_space(10.0);
-    }
-
-    if device.get_bluetooth_config().is_some() {
-        Checkbox::new(&mut headset.auto_enable_bluetooth, "Auto Enable Bluetooth")
-            .ui(ui)
-            .submit(debouncer, auto_update, Action::UpdateAutoBluetooth);
-        ui.add_space(10.0);
-        let actions = [
-            (CallAction::Nothing, "Nothing"),
-            (CallAction::ReduceVolume, "Reduce Volume"),
-            (CallAction::Mute, "Mute")
-        ];
-        let mut current_index = actions
-            .iter()
-            .position(|(a, _)| *a == headset.bluetooth_call)
-            .unwrap_or(0);
-        ComboBox::from_label("Bluetooth Call Action")
-            .width(120.0)
-            .show_index(ui, &mut current_index, actions.len(), |i| actions[i].1.to_string())
-            .submit(debouncer, auto_update, Action::UpdateBluetoothCall);
-        headset.bluetooth_call = actions[current_index].0;
+    if device.get_equalizer().is_some() {
+        equalizer_presets_ui(ui, debouncer, headset);
         ui.add_space(10.0);
     }
 
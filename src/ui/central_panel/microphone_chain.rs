@@ -0,0 +1,72 @@
+use egui::*;
+
+use crate::config::MicrophoneChain;
+use crate::debouncer::{Action, Debouncer};
+
+/// `error` explains why the enabled chain is not running
+pub fn microphone_chain_ui(ui: &mut Ui, debouncer: &mut Debouncer, auto_update: bool, microphone: &mut MicrophoneChain, error: Option<String>) {
+    let mut dirty = false;
+    CollapsingHeader::new("Microphone Processing").show(ui, |ui| {
+        dirty |= ui
+            .checkbox(&mut microphone.enabled, "Enabled")
+            .on_hover_text("Creates a virtual microphone that processes the capture device of the headset.")
+            .changed();
+        ui.add_enabled_ui(microphone.enabled, |ui| {
+            ui.horizontal(|ui| {
+                dirty |= ui.checkbox(&mut microphone.noise_gate, "Noise Gate").changed();
+                dirty |= ui
+                    .add_enabled(
+                        microphone.noise_gate,
+                        DragValue::new(&mut microphone.gate_threshold)
+                            .speed(0.5)
+                            .clamp_range(-70.0..=0.0)
+                            .prefix("Threshold: ")
+                            .suffix(" dB")
+                    )
+                    .on_hover_text("Signals below this level are muted.")
+                    .changed();
+            });
+            ui.horizontal(|ui| {
+                dirty |= ui.checkbox(&mut microphone.compressor, "Compressor").changed();
+                ui.add_enabled_ui(microphone.compressor, |ui| {
+                    dirty |= ui
+                        .add(
+                            DragValue::new(&mut microphone.compressor_threshold)
+                                .speed(0.5)
+                                .clamp_range(-30.0..=0.0)
+                                .prefix("Threshold: ")
+                                .suffix(" dB")
+                        )
+                        .changed();
+                    dirty |= ui
+                        .add(
+                            DragValue::new(&mut microphone.compressor_ratio)
+                                .speed(0.1)
+                                .clamp_range(1.0..=20.0)
+                                .prefix("Ratio: 1:")
+                        )
+                        .changed();
+                });
+            });
+            dirty |= ui
+                .add(
+                    DragValue::new(&mut microphone.gain)
+                        .speed(0.1)
+                        .clamp_range(-20.0..=20.0)
+                        .prefix("Gain: ")
+                        .suffix(" dB")
+                )
+                .changed();
+        });
+        ui.label("Requires the swh LADSPA plugins for the noise gate and the compressor.");
+        if let Some(err) = error.filter(|_| microphone.enabled) {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+    });
+    if dirty {
+        debouncer.submit(Action::SaveConfig);
+        if auto_update {
+            debouncer.submit(Action::UpdateMicrophoneChain);
+        }
+    }
+}
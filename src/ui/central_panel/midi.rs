@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use egui::*;
+use tracing::instrument;
+
+use crate::config::{MidiConfig, MidiTarget};
+use crate::debouncer::{Action, Debouncer};
+use crate::devices::{Device, SettingKind};
+
+fn targets(device: &dyn Device, profiles: &[String]) -> Vec<(MidiTarget, String)> {
+    let settings = device
+        .settings()
+        .into_iter()
+        .filter(|setting| !matches!(setting.kind, SettingKind::Bands { .. }))
+        .map(|setting| (MidiTarget::Setting(setting.id), setting.label.to_string()));
+    let bands = device
+        .get_equalizer()
+        .map_or(0, |equalizer| equalizer.bands());
+    let bands = (0..bands).map(|band| (MidiTarget::EqualizerBand(band), format!("Equalizer Band {}", band + 1)));
+    let profiles = profiles
+        .iter()
+        .map(|name| (MidiTarget::Profile(name.clone()), format!("Profile \"{}\"", name)));
+    settings.chain(bands).chain(profiles).collect()
+}
+
+#[instrument(skip_all)]
+pub fn midi_ui(ui: &mut Ui, debouncer: &mut Debouncer, midi: &mut MidiConfig, profiles: &[String], device: &dyn Device) {
+    CollapsingHeader::new("MIDI Controls").show(ui, |ui| {
+        if midi.learn.is_some() {
+            ui.label("Move a knob or press a button on your controller...");
+            // The assignment happens outside of the ui, so keep checking for it
+            ui.ctx().request_repaint_after(Duration::from_millis(200));
+        }
+        Grid::new("midi_mappings")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for (target, label) in targets(device, profiles) {
+                    ui.label(label);
+                    match midi.control(&target) {
+                        Some(control) => ui.label(control.to_string()),
+                        None => ui.weak("Unassigned")
+                    };
+                    ui.horizontal(|ui| {
+                        if midi.learn.as_ref() == Some(&target) {
+                            if ui.button("Cancel").clicked() {
+                                midi.learn = None;
+                            }
+                        } else if ui.button("Learn").clicked() {
+                            midi.learn = Some(target.clone());
+                        }
+                        if midi.control(&target).is_some() && ui.button("Clear").clicked() {
+                            midi.clear(&target);
+                            debouncer.submit(Action::SaveConfig);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+    });
+}
@@ -1,5 +1,14 @@
+#[cfg(target_os = "linux")]
+mod auto_profile;
+mod equalizer;
 mod headset;
+mod microphone_chain;
+mod midi;
+mod notifications;
 mod profile;
+mod schedule;
+mod settings;
+mod software_equalizer;
 
 use egui::*;
 use tracing::instrument;
@@ -9,8 +18,13 @@ use crate::config::Config;
 use crate::debouncer::{Action, Debouncer};
 use crate::devices::Device;
 use crate::submit_full_change;
+#[cfg(target_os = "linux")]
+use crate::ui::central_panel::auto_profile::auto_profile_ui;
 use crate::ui::central_panel::headset::headset_section;
+use crate::ui::central_panel::midi::midi_ui;
+use crate::ui::central_panel::notifications::notifications_ui;
 use crate::ui::central_panel::profile::profile_section;
+use crate::ui::central_panel::schedule::schedules_ui;
 
 #[instrument(skip_all)]
 pub fn central_panel(ui: &mut Ui, debouncer: &mut Debouncer, config: &mut Config, device: &dyn Device, audio_system: &mut AudioSystem) {
@@ -34,13 +48,19 @@ pub fn central_panel(ui: &mut Ui, debouncer: &mut Debouncer, config: &mut Config
         let headset = config.get_headset(device.name());
         ui.heading("Profile");
         ui.add_space(7.0);
-        profile_section(ui, debouncer, auto_update, headset.selected_profile(), device);
+        profile_section(ui, debouncer, auto_update, headset, device, audio_system);
         ui.add_space(10.0);
+        #[cfg(target_os = "linux")]
+        {
+            auto_profile_ui(ui, debouncer, headset);
+            ui.add_space(10.0);
+        }
         ui.separator();
         ui.add_space(10.0);
         ui.heading("Headset");
         ui.add_space(7.0);
         headset_section(ui, debouncer, auto_update, headset, device, audio_system);
+        schedules_ui(ui, debouncer, headset, device);
         ui.add_space(10.0);
         ui.separator();
         ui.add_space(10.0);
@@ -57,10 +77,22 @@ pub fn central_panel(ui: &mut Ui, debouncer: &mut Debouncer, config: &mut Config
                 .add_sized([200.0, 20.0], Button::new("Apply Now"))
                 .clicked()
             {
-                submit_full_change(debouncer);
+                submit_full_change(debouncer, device);
             }
         });
         ui.add_space(10.0);
+        notifications_ui(ui, debouncer, &mut config.notifications);
+        ui.add_space(10.0);
+        if config.midi.enabled {
+            let profiles = config
+                .get_headset(device.name())
+                .profiles
+                .iter()
+                .map(|profile| profile.name.clone())
+                .collect::<Vec<_>>();
+            midi_ui(ui, debouncer, &mut config.midi, &profiles, device);
+            ui.add_space(10.0);
+        }
         #[cfg(target_os = "windows")]
         {
             let mut auto_start = autostart::is_enabled()
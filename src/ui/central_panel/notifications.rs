@@ -0,0 +1,124 @@
+use egui::*;
+use tracing::instrument;
+
+use crate::config::{NotificationConfig, NotificationEvent, NotificationSettings, NotificationTarget, NotificationUrgency};
+use crate::debouncer::{Action, Debouncer};
+
+fn thresholds_ui(ui: &mut Ui, thresholds: &mut Vec<u8>) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Alert at");
+        let mut removed = None;
+        for (i, threshold) in thresholds.iter_mut().enumerate() {
+            changed |= ui
+                .add(DragValue::new(threshold).clamp_range(1..=99).suffix("%"))
+                .changed();
+            if ui.small_button("x").clicked() {
+                removed = Some(i);
+            }
+        }
+        if let Some(i) = removed {
+            thresholds.remove(i);
+            changed = true;
+        }
+        if ui.small_button("+").clicked() {
+            thresholds.push(10);
+            changed = true;
+        }
+    });
+    changed
+}
+
+fn target_name(target: &NotificationTarget) -> &'static str {
+    match target {
+        NotificationTarget::Desktop => "Desktop",
+        NotificationTarget::Log => "Log Only",
+        NotificationTarget::Hook(_) => "Hook"
+    }
+}
+
+fn event_ui(ui: &mut Ui, event: NotificationEvent, settings: &mut NotificationSettings) -> bool {
+    let mut changed = false;
+    Grid::new(("notification", event))
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Title");
+            changed |= ui
+                .add(TextEdit::singleline(&mut settings.title).hint_text(event.default_title()))
+                .changed();
+            ui.end_row();
+            ui.label("Text");
+            changed |= ui
+                .add(TextEdit::singleline(&mut settings.template).hint_text(event.default_template()))
+                .changed();
+            ui.end_row();
+            ui.label("Duration");
+            changed |= ui
+                .add(
+                    DragValue::new(&mut settings.duration_ms)
+                        .clamp_range(500..=60000)
+                        .speed(100)
+                        .suffix(" ms")
+                )
+                .changed();
+            ui.end_row();
+            ui.label("Urgency");
+            ComboBox::from_id_source(("notification_urgency", event))
+                .selected_text(format!("{:?}", settings.urgency))
+                .show_ui(ui, |ui| {
+                    for urgency in NotificationUrgency::URGENCIES {
+                        changed |= ui
+                            .selectable_value(&mut settings.urgency, urgency, format!("{:?}", urgency))
+                            .changed();
+                    }
+                });
+            ui.end_row();
+            ui.label("Show In");
+            ComboBox::from_id_source(("notification_target", event))
+                .selected_text(target_name(&settings.target))
+                .show_ui(ui, |ui| {
+                    for target in [NotificationTarget::Desktop, NotificationTarget::Log, NotificationTarget::Hook(String::new())] {
+                        let selected = target_name(&settings.target) == target_name(&target);
+                        if ui.selectable_label(selected, target_name(&target)).clicked() && !selected {
+                            settings.target = target;
+                            changed = true;
+                        }
+                    }
+                });
+            ui.end_row();
+            if let NotificationTarget::Hook(command) = &mut settings.target {
+                ui.label("Command");
+                changed |= ui
+                    .add(TextEdit::singleline(command).hint_text("notify-send \"$HEADSET_TITLE\" \"$HEADSET_BODY\""))
+                    .changed();
+                ui.end_row();
+            }
+        });
+    changed
+}
+
+#[instrument(skip_all)]
+pub fn notifications_ui(ui: &mut Ui, debouncer: &mut Debouncer, notifications: &mut NotificationConfig) {
+    CollapsingHeader::new("Notifications").show(ui, |ui| {
+        let mut changed = false;
+        for event in NotificationEvent::EVENTS {
+            let settings = notifications.event_mut(event);
+            changed |= ui.checkbox(&mut settings.enabled, event.label()).changed();
+            if !settings.enabled {
+                continue;
+            }
+            ui.indent(("notification_settings", event), |ui| {
+                CollapsingHeader::new("Customize")
+                    .id_source(("notification_customize", event))
+                    .show(ui, |ui| changed |= event_ui(ui, event, settings));
+            });
+            if event == NotificationEvent::LowBattery {
+                ui.indent("notification_thresholds", |ui| changed |= thresholds_ui(ui, &mut notifications.battery_thresholds));
+            }
+        }
+        ui.weak("The texts support {device}, {battery}, {charging}, {game}, {chat} and {profile}, and {settings} when the profile changes.");
+        if changed {
+            debouncer.submit(Action::SaveConfig);
+        }
+    });
+}
@@ -1,85 +1,30 @@
 use egui::*;
 use tracing::instrument;
 
-use crate::config::{EqualizerConfig, Profile};
-use crate::debouncer::{Action, Debouncer};
-use crate::devices::{Device, Equalizer};
-use crate::ui::ResponseExt;
+use crate::audio::AudioSystem;
+use crate::config::HeadsetConfig;
+use crate::debouncer::Debouncer;
+use crate::devices::{Device, SettingScope};
+use crate::ui::central_panel::microphone_chain::microphone_chain_ui;
+use crate::ui::central_panel::settings::setting_ui;
+use crate::ui::central_panel::software_equalizer::software_equalizer_ui;
 
 #[instrument(skip_all)]
-pub fn profile_section(ui: &mut Ui, debouncer: &mut Debouncer, auto_update: bool, profile: &mut Profile, device: &dyn Device) {
-    if let Some(equalizer) = device.get_equalizer() {
-        equalizer_ui(ui, debouncer, auto_update, &mut profile.equalizer, equalizer);
-        ui.add_space(10.0);
-    }
-    if let Some(side_tone) = device.get_side_tone() {
-        Slider::new(&mut profile.side_tone, 0..=(side_tone.levels() - 1))
-            .text("Side Tone Level")
-            .ui(ui)
-            .on_hover_text("This setting controls how much of your voice is played back over the headset when you speak.\nSet to 0 to turn off.")
-            .submit(debouncer, auto_update, Action::UpdateSideTone);
-        ui.add_space(10.0);
-    }
-    if let Some(mic_volume) = device.get_mic_volume() {
-        Slider::new(&mut profile.microphone_volume, 0..=(mic_volume.levels() - 1))
-            .text("Microphone Level")
-            .ui(ui)
-            .submit(debouncer, auto_update, Action::UpdateMicrophoneVolume);
-        ui.add_space(10.0);
-    }
-    if device.get_volume_limiter().is_some() {
-        Checkbox::new(&mut profile.volume_limiter, "Limit Volume")
-            .ui(ui)
-            .submit(debouncer, auto_update, Action::UpdateVolumeLimit);
-        ui.add_space(10.0);
-    }
-}
-
-fn equalizer_ui(ui: &mut Ui, debouncer: &mut Debouncer, auto_update: bool, conf: &mut EqualizerConfig, equalizer: &dyn Equalizer) {
-    let range = (equalizer.base_level() - equalizer.variance())..=(equalizer.base_level() + equalizer.variance());
-    let mut presets = equalizer
-        .presets()
+pub fn profile_section(
+    ui: &mut Ui, debouncer: &mut Debouncer, auto_update: bool, headset: &mut HeadsetConfig, device: &dyn Device, audio_system: &AudioSystem
+) {
+    let (profile, user_presets) = headset.selected_profile_and_presets();
+    for setting in device
+        .settings()
         .iter()
-        .map(|(s, _)| s.to_string())
-        .collect::<Vec<_>>();
-    let custom_index = presets.len();
-    presets.push("Custom".to_string());
-    let (mut current_index, mut levels) = match conf {
-        EqualizerConfig::Preset(i) => (*i as usize, equalizer.presets()[*i as usize].1.to_vec()),
-        EqualizerConfig::Custom(levels) => (custom_index, levels.clone())
-    };
-    let preset = ComboBox::from_label("Equalizer").show_index(ui, &mut current_index, presets.len(), |i| presets[i].clone());
-    let mut dirty = preset.changed();
-    ui.horizontal(|ui| {
-        ui.style_mut().spacing.slider_width = 150.0;
-        ui.style_mut().spacing.button_padding = vec2(5.0, 2.0);
-        ui.style_mut().spacing.interact_size = vec2(30.0, 20.0);
-        for i in levels.iter_mut() {
-            let resp = Slider::new(i, range.clone())
-                .vertical()
-                .trailing_fill(true)
-                .ui(ui);
-            if resp.changed() {
-                dirty |= true;
-                current_index = custom_index;
-            }
-            if resp.drag_released() {
-                debouncer.force(Action::UpdateEqualizer);
-            }
-        }
-    });
-    if dirty {
-        *conf = if current_index == custom_index {
-            EqualizerConfig::Custom(levels)
-        } else {
-            EqualizerConfig::Preset(current_index as u32)
-        };
-        debouncer.submit(Action::SaveConfig);
-        if auto_update {
-            debouncer.submit(Action::UpdateEqualizer);
-        }
+        .filter(|setting| setting.scope == SettingScope::Profile)
+    {
+        setting_ui(ui, debouncer, auto_update, setting, profile.settings.value(setting, user_presets), user_presets, device);
+        ui.add_space(10.0);
     }
-    if preset.changed() {
-        debouncer.force(Action::UpdateEqualizer);
+    if AudioSystem::supports_filter_chains() {
+        software_equalizer_ui(ui, debouncer, auto_update, &mut profile.software_equalizer);
+        let error = audio_system.microphone_chain_error();
+        microphone_chain_ui(ui, debouncer, auto_update, &mut profile.microphone_chain, error);
     }
 }
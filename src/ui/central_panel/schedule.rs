@@ -0,0 +1,127 @@
+use egui::*;
+use tracing::instrument;
+
+use crate::config::{Day, HeadsetConfig, Schedule, ScheduledSetting};
+use crate::debouncer::{Action, Debouncer};
+use crate::devices::{Device, SettingDescriptor, SettingKind};
+
+fn schedule_ui(ui: &mut Ui, id: usize, schedule: &mut Schedule, profiles: &[String], settings: &[SettingDescriptor]) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        changed |= ui.checkbox(&mut schedule.enabled, "").changed();
+        changed |= ui.text_edit_singleline(&mut schedule.name).changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("At");
+        changed |= ui
+            .add(DragValue::new(&mut schedule.time.hour).clamp_range(0..=23))
+            .changed();
+        ui.label(":");
+        changed |= ui
+            .add(DragValue::new(&mut schedule.time.minute).clamp_range(0..=59))
+            .changed();
+        for day in Day::DAYS {
+            let selected = schedule.days.contains(&day);
+            let label = &format!("{:?}", day)[..3];
+            if ui.selectable_label(selected, label).clicked() {
+                match selected {
+                    true => schedule.days.retain(|d| *d != day),
+                    false => schedule.days.push(day)
+                }
+                changed = true;
+            }
+        }
+        if schedule.days.is_empty() {
+            ui.weak("(every day)");
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Profile");
+        ComboBox::from_id_source(("schedule_profile", id))
+            .selected_text(schedule.profile.as_deref().unwrap_or("Unchanged"))
+            .show_ui(ui, |ui| {
+                changed |= ui
+                    .selectable_value(&mut schedule.profile, None, "Unchanged")
+                    .changed();
+                for profile in profiles {
+                    changed |= ui
+                        .selectable_value(&mut schedule.profile, Some(profile.clone()), profile)
+                        .changed();
+                }
+            });
+    });
+    let mut removed = None;
+    for (i, setting) in schedule.settings.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            let label = settings
+                .iter()
+                .find(|descriptor| descriptor.id == setting.setting)
+                .map_or("Unsupported", |descriptor| descriptor.label);
+            ComboBox::from_id_source(("schedule_setting", id, i))
+                .selected_text(label)
+                .show_ui(ui, |ui| {
+                    for descriptor in settings {
+                        changed |= ui
+                            .selectable_value(&mut setting.setting, descriptor.id, descriptor.label)
+                            .changed();
+                    }
+                });
+            changed |= ui
+                .add(TextEdit::singleline(&mut setting.value).hint_text("Value"))
+                .changed();
+            if ui.button("Remove").clicked() {
+                removed = Some(i);
+            }
+        });
+    }
+    if let Some(i) = removed {
+        schedule.settings.remove(i);
+        changed = true;
+    }
+    if let Some(first) = settings.first() {
+        if ui.button("Add Setting").clicked() {
+            schedule.settings.push(ScheduledSetting {
+                setting: first.id,
+                value: String::new()
+            });
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[instrument(skip_all)]
+pub fn schedules_ui(ui: &mut Ui, debouncer: &mut Debouncer, headset: &mut HeadsetConfig, device: &dyn Device) {
+    let profiles = headset
+        .profiles
+        .iter()
+        .map(|profile| profile.name.clone())
+        .collect::<Vec<_>>();
+    let settings = device
+        .settings()
+        .into_iter()
+        .filter(|setting| !matches!(setting.kind, SettingKind::Bands { .. }))
+        .collect::<Vec<_>>();
+    CollapsingHeader::new("Schedules").show(ui, |ui| {
+        let mut changed = false;
+        let mut removed = None;
+        for (i, schedule) in headset.schedules.iter_mut().enumerate() {
+            changed |= schedule_ui(ui, i, schedule, &profiles, &settings);
+            if ui.button("Remove Schedule").clicked() {
+                removed = Some(i);
+            }
+            ui.separator();
+        }
+        if let Some(i) = removed {
+            headset.schedules.remove(i);
+            changed = true;
+        }
+        if ui.button("Add Schedule").clicked() {
+            headset.schedules.push(Schedule::default());
+            changed = true;
+        }
+        if changed {
+            debouncer.submit(Action::SaveConfig);
+        }
+    });
+}
@@ -0,0 +1,48 @@
+use egui::*;
+use tracing::instrument;
+
+use crate::config::EqualizerPreset;
+use crate::debouncer::{Action, Debouncer};
+use crate::devices::{Device, SettingDescriptor, SettingKind, SettingValue};
+use crate::ui::central_panel::equalizer::equalizer_ui;
+use crate::ui::ResponseExt;
+
+#[instrument(skip_all, fields(id = ?setting.id))]
+pub fn setting_ui(
+    ui: &mut Ui, debouncer: &mut Debouncer, auto_update: bool, setting: &SettingDescriptor, value: &mut SettingValue,
+    user_presets: &mut Vec<EqualizerPreset>, device: &dyn Device
+) {
+    let response = match (&setting.kind, value) {
+        (SettingKind::Slider { min, max }, SettingValue::Level(level)) => Slider::new(level, *min..=*max)
+            .text(setting.label)
+            .ui(ui),
+        (SettingKind::Toggle, SettingValue::Toggle(enabled)) => Checkbox::new(enabled, setting.label).ui(ui),
+        (SettingKind::Enum { options }, SettingValue::Choice(choice)) => {
+            let mut current_index = *choice as usize;
+            let response = ComboBox::from_label(setting.label)
+                .width(120.0)
+                .show_index(ui, &mut current_index, options.len(), |i| options[i].to_string());
+            *choice = current_index as u8;
+            response
+        }
+        (SettingKind::Bands { .. }, SettingValue::Bands(conf)) => {
+            let SettingValue::Bands(default) = &setting.default else {
+                tracing::warn!("{:?} has no equalizer default", setting.id);
+                return;
+            };
+            if let Some(equalizer) = device.get_equalizer() {
+                equalizer_ui(ui, debouncer, auto_update, conf, default, user_presets, equalizer);
+            }
+            return;
+        }
+        (_, value) => {
+            tracing::warn!("Can not display {:?} as {:?}", value, setting.kind);
+            return;
+        }
+    };
+    let response = match setting.tooltip {
+        Some(tooltip) => response.on_hover_text(tooltip),
+        None => response
+    };
+    response.submit(debouncer, auto_update, Action::UpdateSetting(setting.id));
+}
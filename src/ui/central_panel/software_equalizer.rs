@@ -0,0 +1,119 @@
+use std::path::Path;
+
+use egui::plot::Line;
+use egui::*;
+
+use crate::autoeq::parse_parametric_eq;
+use crate::config::SoftwareEqualizer;
+use crate::debouncer::{Action, Debouncer};
+use crate::dsp::{FilterKind, ParametricFilter, MAX_FREQUENCY, MIN_FREQUENCY};
+use crate::ui::central_panel::equalizer::{response_curve, response_plot};
+
+const FILTER_KINDS: [(FilterKind, &str); 3] = [
+    (FilterKind::Peaking, "Peaking"),
+    (FilterKind::LowShelf, "Low Shelf"),
+    (FilterKind::HighShelf, "High Shelf")
+];
+
+pub fn software_equalizer_ui(ui: &mut Ui, debouncer: &mut Debouncer, auto_update: bool, equalizer: &mut SoftwareEqualizer) {
+    let mut dirty = false;
+    CollapsingHeader::new("Software Equalizer").show(ui, |ui| {
+        dirty |= ui
+            .checkbox(&mut equalizer.enabled, "Enabled")
+            .on_hover_text("Runs a PipeWire filter-chain in front of the headset and exposes it as a separate output device.")
+            .changed();
+        dirty |= ui
+            .add(DragValue::new(&mut equalizer.preamp).speed(0.1).clamp_range(-30.0..=30.0).suffix(" dB").prefix("Preamp: "))
+            .changed();
+        let mut removed = None;
+        for (i, filter) in equalizer.filters.iter_mut().enumerate() {
+            ui.push_id(i, |ui| {
+                ui.horizontal(|ui| {
+                    let mut kind = FILTER_KINDS.iter().position(|(kind, _)| *kind == filter.kind).unwrap_or(0);
+                    if ComboBox::from_id_source("kind")
+                        .width(90.0)
+                        .show_index(ui, &mut kind, FILTER_KINDS.len(), |i| FILTER_KINDS[i].1.to_string())
+                        .changed()
+                    {
+                        filter.kind = FILTER_KINDS[kind].0;
+                        dirty = true;
+                    }
+                    dirty |= ui
+                        .add(DragValue::new(&mut filter.frequency).speed(5.0).clamp_range(MIN_FREQUENCY..=MAX_FREQUENCY).suffix(" Hz"))
+                        .changed();
+                    dirty |= ui
+                        .add(DragValue::new(&mut filter.gain).speed(0.1).clamp_range(-30.0..=30.0).suffix(" dB"))
+                        .changed();
+                    dirty |= ui
+                        .add(DragValue::new(&mut filter.q).speed(0.01).clamp_range(0.1..=10.0).prefix("Q "))
+                        .changed();
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                });
+            });
+        }
+        if let Some(i) = removed {
+            equalizer.filters.remove(i);
+            dirty = true;
+        }
+        if ui.button("Add Filter").clicked() {
+            equalizer.filters.push(ParametricFilter {
+                kind: FilterKind::Peaking,
+                frequency: 1000.0,
+                gain: 0.0,
+                q: 1.0
+            });
+            dirty = true;
+        }
+        let filters = equalizer
+            .filters
+            .iter()
+            .map(|filter| filter.biquad())
+            .collect::<Vec<_>>();
+        let max_gain = equalizer
+            .filters
+            .iter()
+            .map(|filter| filter.gain.abs())
+            .fold(6.0, f64::max);
+        response_plot(ui, "software_equalizer_response", max_gain, vec![Line::new(response_curve(&filters))]);
+        dirty |= load_ui(ui, equalizer);
+    });
+    if dirty {
+        debouncer.submit(Action::SaveConfig);
+        if auto_update {
+            debouncer.submit(Action::UpdateSoftwareEqualizer);
+        }
+    }
+}
+
+fn load_ui(ui: &mut Ui, equalizer: &mut SoftwareEqualizer) -> bool {
+    let id = ui.make_persistent_id("software_equalizer_load");
+    let (mut path, mut error) = ui
+        .data_mut(|data| data.get_temp::<(String, Option<String>)>(id))
+        .unwrap_or_default();
+    let mut loaded = false;
+    ui.horizontal(|ui| {
+        ui.add(TextEdit::singleline(&mut path).hint_text("Path to ParametricEQ.txt"))
+            .on_hover_text("Replaces the filters with the ones from an Equalizer APO file.");
+        if ui.button("Load").clicked() {
+            let result = std::fs::read_to_string(Path::new(path.trim().trim_matches('"')))
+                .map_err(|err| format!("Can not read file: {}", err))
+                .and_then(|text| parse_parametric_eq(&text).map_err(|err| format!("Can not parse file: {}", err)));
+            match result {
+                Ok(parametric_eq) => {
+                    equalizer.preamp = parametric_eq.preamp;
+                    equalizer.filters = parametric_eq.filters;
+                    error = None;
+                    loaded = true;
+                }
+                Err(err) => error = Some(err)
+            }
+        }
+    });
+    if let Some(err) = &error {
+        ui.colored_label(ui.visuals().error_fg_color, err);
+    }
+    ui.data_mut(|data| data.insert_temp(id, (path, error)));
+    loaded
+}
@@ -2,15 +2,16 @@ mod central_panel;
 mod side_panel;
 
 use egui::panel::Side;
-use egui::{CentralPanel, Context, Response, RichText, SidePanel};
+use egui::{Align, CentralPanel, ComboBox, Context, Layout, Response, RichText, SidePanel, TopBottomPanel};
 use once_cell::sync::Lazy;
 use tao::window::Icon;
 use tracing::instrument;
 
 use crate::audio::AudioSystem;
-use crate::config::Config;
+use crate::config::{Config, NoiseCancellingMode};
 use crate::debouncer::{Action, Debouncer};
 use crate::devices::{Device, SupportedDevice};
+use crate::tray::{TrayEvent, TrayMenuEntries};
 use crate::ui::central_panel::central_panel;
 use crate::ui::side_panel::side_panel;
 
@@ -56,6 +57,51 @@ pub fn no_device_ui(ctx: &Context, debouncer: &mut Debouncer) {
     });
 }
 
+/// Replaces the tray menu while no tray is available
+#[instrument(skip_all)]
+pub fn tray_fallback_ui(ctx: &Context, entries: &TrayMenuEntries) -> Option<TrayEvent> {
+    let mut event = None;
+    TopBottomPanel::bottom("TrayFallback").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("No system tray available")
+                .on_hover_text("Closing this window quits the application");
+            ui.separator();
+            if !entries.profiles.is_empty() {
+                let selected = entries
+                    .profiles
+                    .get(entries.selected)
+                    .map_or("", String::as_str);
+                ComboBox::from_label("Profile")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for (i, name) in entries.profiles.iter().enumerate() {
+                            if ui.selectable_label(i == entries.selected, name.as_str()).clicked() {
+                                event = Some(TrayEvent::Profile(i));
+                            }
+                        }
+                    });
+            }
+            if let Some(current) = entries.noise_cancelling {
+                ui.separator();
+                for (mode, name) in NoiseCancellingMode::MODES
+                    .into_iter()
+                    .zip(NoiseCancellingMode::NAMES)
+                {
+                    if ui.selectable_label(mode == current, *name).clicked() {
+                        event = Some(TrayEvent::NoiseCancelling(mode));
+                    }
+                }
+            }
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui.button("Quit").clicked() {
+                    event = Some(TrayEvent::Quit);
+                }
+            });
+        });
+    });
+    event
+}
+
 trait ResponseExt {
     fn submit(self, debouncer: &mut Debouncer, auto_update: bool, action: Action) -> Self;
 }
@@ -120,7 +120,7 @@ pub fn side_panel(ui: &mut Ui, debouncer: &mut Debouncer, config: &mut Config, d
                 }
             }
             if headset.selected_profile_index != old_profile_index {
-                submit_profile_change(debouncer);
+                submit_profile_change(debouncer, device);
                 debouncer.submit_all([Action::SaveConfig, Action::UpdateTray]);
             }
         });
@@ -1,7 +1,8 @@
 use std::io::{Error, ErrorKind, Write};
 
 use crossbeam_utils::atomic::AtomicCell;
-use tao::event_loop::EventLoopProxy;
+
+use crate::{AppEvent, EventSender};
 
 pub trait CopySlice<T> {
     fn cloned(self) -> Box<[T]>;
@@ -89,9 +90,12 @@ pub trait SenderExt<T> {
     fn send_log(&self, update: T);
 }
 
-impl<T> SenderExt<T> for EventLoopProxy<T> {
+impl<T> SenderExt<T> for EventSender
+where
+    AppEvent: From<T>
+{
     fn send_log(&self, update: T) {
-        self.send_event(update)
+        self.send_event(AppEvent::from(update))
             .unwrap_or_else(|_| tracing::warn!("Could not send message because the receiver is closed"))
     }
 }
@@ -108,3 +112,12 @@ impl<T> VecExt<T> for Vec<T> {
         self.rotate_right(offset);
     }
 }
+
+/// Replaces every `{name}` in the template with the matching value. Unknown placeholders are kept as they are.
+pub fn fill_template(template: &str, values: &[(&str, String)]) -> String {
+    let mut result = template.to_string();
+    for (name, value) in values {
+        result = result.replace(&format!("{{{}}}", name), value);
+    }
+    result
+}